@@ -0,0 +1,200 @@
+//! Times jump-heavy code, both in a single node on its own and in a whole solution run over many
+//! seeds. Run with `cargo bench`.
+
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tis100::compute::ComputeNode;
+use tis100::grid::{ComputeGrid, Outcome};
+use tis100::instr::{Dst, Instruction, Op, Register, Src};
+use tis100::node::NodeOps;
+
+/// Nested countdown loops, which are almost all jumps.
+const LOOP: &[u8] = b"\
+START: MOV 20, ACC
+OUTER: SAV
+MOV 20, ACC
+INNER: SUB 1
+JGZ INNER
+SWP
+SUB 1
+JEZ START
+JMP OUTER
+";
+
+const LOOP_INSTRUCTIONS: usize = 10_000_000;
+
+/// A solution to Signal Amplifier that spends most of its time in a countdown loop.
+const SOLUTION: &[u8] = b"\
+@0
+
+@1
+MOV UP, DOWN
+
+@2
+
+@3
+
+@4
+S: MOV UP, ACC
+SAV
+MOV 20, ACC
+L: SUB 1
+JGZ L
+SWP
+ADD ACC
+MOV ACC, DOWN
+JMP S
+
+@5
+
+@6
+
+@7
+MOV UP, RIGHT
+
+@8
+MOV LEFT, DOWN
+";
+
+const SEEDS: u8 = 50;
+
+fn report(what: &str, count: usize, elapsed: Duration) {
+    println!("{}: {} in {:.3?}, {:.0}/s",
+        what, count, elapsed, count as f64 / elapsed.as_secs_f64());
+}
+
+fn bench_node() {
+    let mut node = ComputeNode::default();
+    node.load_assembly(tis100::assembly::program_items(LOOP).unwrap().into_iter()).unwrap();
+
+    let start = Instant::now();
+    for _ in 0 .. LOOP_INSTRUCTIONS {
+        node.read::<false>(&mut []).unwrap();
+        node.compute::<false>().unwrap();
+        node.write::<false>().unwrap();
+        node.advance::<false>().unwrap();
+    }
+    report("single node instructions", LOOP_INSTRUCTIONS, start.elapsed());
+}
+
+fn immediate(src: &Src) -> i32 {
+    match src {
+        Src::Immediate(value) => i32::from(*value),
+        _ => panic!("only immediates are handled here"),
+    }
+}
+
+/// Run the loop the way nodes used to, looking up a jump's label every time it's taken. Returns
+/// ACC at the end.
+fn run_by_label(instructions: &[Instruction], labels: &HashMap<String, usize>) -> i32 {
+    let (mut acc, mut bak, mut pc) = (0, 0, 0);
+    for _ in 0 .. LOOP_INSTRUCTIONS {
+        let target = match &instructions[pc] {
+            Instruction::MOV(src, Dst::Register(Register::ACC)) => { acc = immediate(src); None }
+            Instruction::SAV => { bak = acc; None }
+            Instruction::SWP => { std::mem::swap(&mut acc, &mut bak); None }
+            Instruction::SUB(src) => { acc = (acc - immediate(src)).max(-999); None }
+            Instruction::JMP(label) => Some(label),
+            Instruction::JEZ(label) if acc == 0 => Some(label),
+            Instruction::JGZ(label) if acc > 0 => Some(label),
+            Instruction::JEZ(_) | Instruction::JGZ(_) => None,
+            instr => panic!("{} isn't handled here", instr),
+        };
+        pc = match target {
+            Some(label) => labels[label],
+            None => (pc + 1) % instructions.len(),
+        };
+    }
+    acc
+}
+
+/// Run the loop the same way, but with the jump targets already resolved. Returns ACC at the end.
+fn run_linked(ops: &[Op]) -> i32 {
+    let (mut acc, mut bak, mut pc) = (0, 0, 0);
+    for _ in 0 .. LOOP_INSTRUCTIONS {
+        let target = match &ops[pc] {
+            Op::MOV(src, Dst::Register(Register::ACC)) => { acc = immediate(src); None }
+            Op::SAV => { bak = acc; None }
+            Op::SWP => { std::mem::swap(&mut acc, &mut bak); None }
+            Op::SUB(src) => { acc = (acc - immediate(src)).max(-999); None }
+            Op::JMP(target) => Some(*target),
+            Op::JEZ(target) if acc == 0 => Some(*target),
+            Op::JGZ(target) if acc > 0 => Some(*target),
+            Op::JEZ(_) | Op::JGZ(_) => None,
+            op => panic!("{:?} isn't handled here", op),
+        };
+        pc = match target {
+            Some(target) => target,
+            None => (pc + 1) % ops.len(),
+        };
+    }
+    acc
+}
+
+/// The same loop run by label lookups and by linked jumps, which is the difference linking makes.
+fn bench_jump_targets() {
+    let mut node = ComputeNode::default();
+    node.load_assembly(tis100::assembly::program_items(LOOP).unwrap().into_iter()).unwrap();
+
+    let start = Instant::now();
+    let by_label = black_box(run_by_label(black_box(&node.instructions), black_box(&node.labels)));
+    let by_label_time = start.elapsed();
+    report("instructions with label lookups", LOOP_INSTRUCTIONS, by_label_time);
+
+    let start = Instant::now();
+    let linked = black_box(run_linked(black_box(&node.ops)));
+    let linked_time = start.elapsed();
+    report("instructions with linked jumps", LOOP_INSTRUCTIONS, linked_time);
+
+    assert_eq!(by_label, linked);
+    println!("linked jumps run {:.2}x as fast",
+        by_label_time.as_secs_f64() / linked_time.as_secs_f64());
+}
+
+fn solution_grid(seed: u8) -> ComputeGrid {
+    let nodes = tis100::assembly::parse_save_file(SOLUTION).unwrap();
+    let rng = rand_chacha::ChaChaRng::from_seed([seed; 32]);
+    let puzzle = tis100::puzzles::get_puzzle("10981", rng).unwrap();
+    let mut grid = ComputeGrid::from_puzzle(puzzle);
+    grid.program_nodes(nodes).unwrap();
+    grid
+}
+
+fn bench_grid() {
+    let start = Instant::now();
+    let mut total_cycles = 0;
+    for seed in 0 .. SEEDS {
+        let mut grid = solution_grid(seed);
+        loop {
+            total_cycles += 1;
+            match grid.step().unwrap() {
+                None => (),
+                Some(Outcome::Correct) => break,
+                Some(outcome) => panic!("seed {}: {}", seed, outcome),
+            }
+        }
+    }
+    report(&format!("cycles of {} puzzle runs, stepping", SEEDS), total_cycles, start.elapsed());
+}
+
+fn bench_run_to_completion() {
+    let start = Instant::now();
+    let mut total_cycles = 0;
+    for seed in 0 .. SEEDS {
+        match solution_grid(seed).run_to_completion(None).unwrap() {
+            (Some(Outcome::Correct), cycles) => { total_cycles += cycles; }
+            (outcome, _) => panic!("seed {}: {:?}", seed, outcome),
+        }
+    }
+    report(&format!("cycles of {} puzzle runs, to completion", SEEDS), total_cycles,
+        start.elapsed());
+}
+
+fn main() {
+    bench_jump_targets();
+    bench_node();
+    bench_grid();
+    bench_run_to_completion();
+}
@@ -16,28 +16,14 @@ fn main() {
 
     let input = fs::read(path).expect("failed to read file");
 
-    match tis100::assembly::parse_save_file(&input) {
-        Ok(map) => {
-            print_program(&map);
-        }
-        Err((remaining, nodes)) => {
-            let pos = input.len() - remaining.len();
-            let (line, col) = input.iter()
-                .take(pos)
-                .fold((1, 0), |(mut line, mut col), byte| {
-                    if *byte == b'\n' {
-                        line += 1;
-                        col = 0;
-                    } else {
-                        col += 1;
-                    }
-                    (line, col)
-                });
-            eprintln!("parse error at {}:{} (offset {})", line, col, pos);
-            eprintln!("parsed input up to that point:");
-            print_program(&nodes);
-        }
+    let (nodes, errors) = tis100::assembly::parse_save_file_partial(&input);
+    for e in &errors {
+        eprintln!("parse error at {}", e);
+    }
+    if !errors.is_empty() {
+        eprintln!("parsed input, skipping lines with errors:");
     }
+    print_program(&nodes);
 }
 
 fn print_program(nodes: &Nodes) {
@@ -3,27 +3,30 @@ use nom::IResult;
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while};
 use nom::character::complete::{digit1, line_ending, multispace1, space0, space1};
-use nom::combinator::{opt, map, map_res, recognize, value};
-use nom::error::ParseError;
-use nom::multi::{fold_many1, many0, many0_count, many1_count};
-use nom::sequence::tuple;
+use nom::combinator::{cut, fail, opt, map, map_res, recognize, value, verify};
+use nom::error::{context, ParseError as _, VerboseError, VerboseErrorKind};
+use nom::multi::{many0_count, many1_count};
+use nom::sequence::{preceded, tuple};
 use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
 
-fn node_id(input: &[u8]) -> IResult<&[u8], u8> {
+pub(crate) type PResult<'a, T> = IResult<&'a [u8], T, VerboseError<&'a [u8]>>;
+
+fn node_id(input: &[u8]) -> PResult<'_, u8> {
     map_res(
         digit1,
         |i: &[u8]| unsafe { std::str::from_utf8_unchecked(i) }.parse::<u8>(),
     )(input)
 }
 
-fn node_tag(input: &[u8]) -> IResult<&[u8], SaveFileNodeId> {
+pub(crate) fn node_tag(input: &[u8]) -> PResult<'_, SaveFileNodeId> {
     let (input, _) = tag(b"@")(input)?;
     let (input, id) = node_id(input)?;
     let (input, _) = line_ending(input)?;
     Ok((input, SaveFileNodeId(id)))
 }
 
-fn port(input: &[u8]) -> IResult<&[u8], Port> {
+fn port(input: &[u8]) -> PResult<'_, Port> {
     alt(
         (
             value(Port::UP, tag(b"UP")),
@@ -36,7 +39,7 @@ fn port(input: &[u8]) -> IResult<&[u8], Port> {
     )(input)
 }
 
-fn register(input: &[u8]) -> IResult<&[u8], Register> {
+fn register(input: &[u8]) -> PResult<'_, Register> {
     alt(
         (
             value(Register::ACC, tag(b"ACC")),
@@ -45,32 +48,28 @@ fn register(input: &[u8]) -> IResult<&[u8], Register> {
     )(input)
 }
 
-fn immediate(input: &[u8]) -> IResult<&[u8], i16> {
-    map_res(
-        recognize(tuple((opt(tag(b"-")), digit1))),
-        |bytes: &[u8]| {
-            let s = unsafe { std::str::from_utf8_unchecked(bytes) };
-            match s.parse::<i16>() {
-                Ok(n) if !(-999 ..= 999).contains(&n) => Err("number out of range"),
-                Err(_) => Err("number out of range"),
-                Ok(n) => Ok(n),
-            }
-        }
-    )(input)
+fn immediate(input: &[u8]) -> PResult<'_, i16> {
+    let (rest, bytes) = recognize(tuple((opt(tag(b"-")), digit1)))(input)?;
+    let s = unsafe { std::str::from_utf8_unchecked(bytes) };
+    match s.parse::<i16>() {
+        Ok(n) if (-999 ..= 999).contains(&n) => Ok((rest, n)),
+        // it's a number, just not one that fits, so say so rather than what else could go here
+        _ => cut(context("number from -999 to 999", fail))(input),
+    }
 }
 
 fn is_label_char(byte: u8) -> bool {
     matches!(byte, b'A' ..= b'Z' | b'0' ..= b'9' | b'-')
 }
 
-fn label(input: &[u8]) -> IResult<&[u8], &str> {
+pub(crate) fn label(input: &[u8]) -> PResult<'_, &str> {
     map(
         take_while(is_label_char),
         |bytes| unsafe { std::str::from_utf8_unchecked(bytes) }
     )(input)
 }
 
-fn source(input: &[u8]) -> IResult<&[u8], Src> {
+fn source(input: &[u8]) -> PResult<'_, Src> {
     alt(
         (
             map(register, Src::Register),
@@ -80,7 +79,7 @@ fn source(input: &[u8]) -> IResult<&[u8], Src> {
     )(input)
 }
 
-fn dest(input: &[u8]) -> IResult<&[u8], Dst> {
+fn dest(input: &[u8]) -> PResult<'_, Dst> {
     alt(
         (
             map(register, Dst::Register),
@@ -89,7 +88,7 @@ fn dest(input: &[u8]) -> IResult<&[u8], Dst> {
     )(input)
 }
 
-fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
+pub(crate) fn comment(input: &[u8]) -> PResult<'_, &[u8]> {
     recognize(
         tuple(
             (
@@ -101,7 +100,7 @@ fn comment(input: &[u8]) -> IResult<&[u8], &[u8]> {
 }
 
 /// Matches any amount of comments, spaces, newlines, but guaranteed at least one newline or EOF.
-fn end_of_line(input: &[u8]) -> IResult<&[u8], &[u8]> {
+fn end_of_line(input: &[u8]) -> PResult<'_, &[u8]> {
     // pro tip: many1_count doesn't like non-capturing parsers, so don't use EOF in it.
     recognize(
         tuple(
@@ -131,7 +130,7 @@ fn end_of_line(input: &[u8]) -> IResult<&[u8], &[u8]> {
 }
 
 /// Matches any amount of comments, spaces, and newlines.
-fn comments_and_whitespace(input: &[u8]) -> IResult<&[u8], ()> {
+fn comments_and_whitespace(input: &[u8]) -> PResult<'_, ()> {
     map(
         many0_count(
             alt(
@@ -145,7 +144,7 @@ fn comments_and_whitespace(input: &[u8]) -> IResult<&[u8], ()> {
     )(input)
 }
 
-fn arg_sep(input: &[u8]) -> IResult<&[u8], &[u8]> {
+fn arg_sep(input: &[u8]) -> PResult<'_, &[u8]> {
     alt(
         (
             recognize(tuple(
@@ -160,103 +159,415 @@ fn arg_sep(input: &[u8]) -> IResult<&[u8], &[u8]> {
     )(input)
 }
 
-fn instruction(input: &[u8]) -> IResult<&[u8], Instruction> {
+pub(crate) fn instruction(input: &[u8]) -> PResult<'_, Instruction> {
+    // Once the instruction name matches, anything wrong with the operands is a hard error, so the
+    // error can say what was expected.
     alt(
         (
             value(Instruction::NOP, tag(b"NOP")),
             |input| {
                 let (input, _) = tag(b"MOV")(input)?;
-                let (input, _) = space1(input)?;
-                let (input, src) = source(input)?;
-                let (input, _) = arg_sep(input)?;
-                let (input, dst) = dest(input)?;
+                let (input, src) = cut(context("port, register or number after MOV",
+                    preceded(space1, source)))(input)?;
+                let (input, _) = cut(context("',' after MOV source", arg_sep))(input)?;
+                let (input, dst) =
+                    cut(context("port or register after MOV's source", dest))(input)?;
                 Ok((input, Instruction::MOV(src, dst)))
             },
             value(Instruction::SWP, tag(b"SWP")),
             value(Instruction::SAV, tag(b"SAV")),
-            map(tuple((tag(b"ADD"), space1, source)), |(_,_,src)| Instruction::ADD(src)),
-            map(tuple((tag(b"SUB"), space1, source)), |(_,_,src)| Instruction::SUB(src)),
+            map(preceded(tag(b"ADD"), cut(context("port, register or number after ADD",
+                preceded(space1, source)))), Instruction::ADD),
+            map(preceded(tag(b"SUB"), cut(context("port, register or number after SUB",
+                preceded(space1, source)))), Instruction::SUB),
             value(Instruction::NEG, tag(b"NEG")),
-            map(tuple(
-                    (
-                        alt({
-                            // Coerce to the general type signature of the Instruction variant
-                            // constructors, instead of the specific function for each one.
-                            let v = |ctor: fn(String) -> Instruction, txt| value(ctor, tag(txt));
-                            (
-                                v(Instruction::JMP, b"JMP"),
-                                v(Instruction::JEZ, b"JEZ"),
-                                v(Instruction::JNZ, b"JNZ"),
-                                v(Instruction::JGZ, b"JGZ"),
-                                v(Instruction::JLZ, b"JLZ"),
-                            )
-                        }),
-                        space1,
-                        label,
-                    )
-                ),
-                |(op_ctor,_,label)| op_ctor(label.to_owned())
-            ),
-            map(tuple((tag(b"JRO"), space1, source)), |(_,_,src)| Instruction::JRO(src)),
+            alt({
+                // Coerce to the general type signature of the Instruction variant constructors,
+                // instead of the specific function for each one.
+                let v = |ctor: fn(String) -> Instruction, txt, ctx| map(
+                    preceded(
+                        tag(txt),
+                        cut(context(ctx, preceded(space1, verify(label, |l: &str| !l.is_empty())))),
+                    ),
+                    move |label| ctor(label.to_owned()));
+                (
+                    v(Instruction::JMP, b"JMP", "label after JMP"),
+                    v(Instruction::JEZ, b"JEZ", "label after JEZ"),
+                    v(Instruction::JNZ, b"JNZ", "label after JNZ"),
+                    v(Instruction::JGZ, b"JGZ", "label after JGZ"),
+                    v(Instruction::JLZ, b"JLZ", "label after JLZ"),
+                )
+            }),
+            map(preceded(tag(b"JRO"), cut(context("port, register or number after JRO",
+                preceded(space1, source)))), Instruction::JRO),
             value(Instruction::HCF, tag(b"HCF")),
         )
     )(input)
 }
 
-fn eof(input: &[u8]) -> IResult<&[u8], ()> {
+fn eof(input: &[u8]) -> PResult<'_, ()> {
     use nom::error::ErrorKind;
     if input.is_empty() {
         Ok((input, ()))
     } else {
-        Err(nom::Err::Error(ParseError::from_error_kind(input, ErrorKind::Eof)))
+        Err(nom::Err::Error(VerboseError::from_error_kind(input, ErrorKind::Eof)))
     }
 }
 
-fn program_item(input: &[u8]) -> IResult<&[u8], ProgramItem> {
+fn program_item(input: &[u8]) -> PResult<'_, ProgramItem> {
     alt(
         (
             map(tuple((comments_and_whitespace, label, tag(b":"), opt(end_of_line))),
                 |(_, label, _, _)| ProgramItem::Label(label.to_owned())),
-            map(tuple((comments_and_whitespace, instruction, end_of_line)),
+            map(tuple((comments_and_whitespace, instruction,
+                    cut(context("end of line or comment", end_of_line)))),
                 |(_, inst, _)| ProgramItem::Instruction(inst)),
             value(ProgramItem::Breakpoint, tuple((tag(b"!"), space0))),
         )
     )(input)
 }
 
-pub fn program_items(input: &[u8]) -> Result<Vec<ProgramItem>, (&[u8], Vec<ProgramItem>)> {
-    let result = many0(program_item)(input);
-    match result {
-        Ok((&[], items)) => Ok(items),
-        Ok((rest, items)) => Err((rest, items)),
-        Err(_) => Err((input, Default::default())),
+/// A syntax error in a save file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The node the error is in, if it comes after a node tag.
+    pub node: Option<SaveFileNodeId>,
+
+    /// Line number, starting at 1.
+    pub line: usize,
+
+    /// Column, starting at 1.
+    pub column: usize,
+
+    /// The text where the error is, up to the next space or comma. Empty at the end of a line.
+    /// If the error is at a comma, it's just the comma.
+    pub text: String,
+
+    /// What should have been there instead.
+    pub expected: &'static str,
+}
+
+impl ParseError {
+    pub(crate) fn new(
+        input: &[u8],
+        error_pos: &[u8],
+        node: Option<SaveFileNodeId>,
+        expected: &'static str,
+    ) -> Self {
+        // Point at the offending text, not the space before it.
+        let spaces = error_pos.iter().take_while(|b| **b == b' ' || **b == b'\t').count();
+        let error_pos = &error_pos[spaces ..];
+        let (line, column) = line_col(input, input.len() - error_pos.len());
+        let text = match error_pos.first() {
+            Some(b',') => b",".to_vec(),
+            _ => error_pos.iter()
+                .take_while(|b| !b" \t\r\n,".contains(b))
+                .cloned()
+                .collect::<Vec<u8>>(),
+        };
+        ParseError {
+            node,
+            line,
+            column,
+            text: String::from_utf8_lossy(&text).into_owned(),
+            expected,
+        }
+    }
+}
+
+/// Find where a nom error happened and what was expected there, from the innermost context it went
+/// through, or a generic message if it didn't have any.
+pub(crate) fn nom_error(err: nom::Err<VerboseError<&[u8]>>) -> (&[u8], &'static str) {
+    let err = match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        nom::Err::Incomplete(_) => unreachable!("incomplete parse should not be possible when using 'complete' parser"),
+    };
+    err.errors.iter()
+        .find_map(|(pos, kind)| match kind {
+            VerboseErrorKind::Context(ctx) => Some((*pos, *ctx)),
+            _ => None,
+        })
+        .unwrap_or((err.errors[0].0, "instruction, label or breakpoint"))
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        if let Some(node) = self.node {
+            write!(f, "node @{}: ", node.0)?;
+        }
+        write!(f, "expected {}, found ", self.expected)?;
+        if self.text.is_empty() {
+            f.write_str("end of line")
+        } else {
+            write!(f, "{:?}", self.text)
+        }
+    }
+}
+
+/// Line and column (both starting at 1) of a byte offset in the input.
+pub fn line_col(input: &[u8], offset: usize) -> (usize, usize) {
+    input.iter()
+        .take(offset)
+        .fold((1, 1), |(mut line, mut col), byte| {
+            if *byte == b'\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+            (line, col)
+        })
+}
+
+/// Skip to the start of the next line.
+fn next_line(input: &[u8]) -> &[u8] {
+    match input.iter().position(|b| *b == b'\n') {
+        Some(pos) => &input[pos + 1 ..],
+        None => &[],
+    }
+}
+
+/// Parse program items for a node until the next node tag or the end of input, skipping over any
+/// lines with errors. Returns the remaining input.
+fn node_items<'a>(
+    input: &'a [u8],
+    mut rest: &'a [u8],
+    node: Option<SaveFileNodeId>,
+    items: &mut Vec<ProgramItem>,
+    errors: &mut Vec<ParseError>,
+) -> &'a [u8] {
+    loop {
+        let (next, ()) = comments_and_whitespace(rest).expect("can't fail");
+        let at_node_tag = node.is_some() && at_line_start(input, next) && node_tag(next).is_ok();
+        if next.is_empty() || at_node_tag {
+            return next;
+        }
+        match program_item(next) {
+            Ok((next, item)) => {
+                items.push(item);
+                rest = next;
+            }
+            Err(e) => {
+                let (pos, expected) = nom_error(e);
+                errors.push(ParseError::new(input, pos, node, expected));
+                rest = next_line(pos);
+            }
+        }
+    }
+}
+
+/// Whether only spaces and tabs come before `pos` on its line of the input.
+fn at_line_start(input: &[u8], pos: &[u8]) -> bool {
+    input[.. input.len() - pos.len()].iter()
+        .rev()
+        .take_while(|b| **b != b'\n')
+        .all(|b| *b == b' ' || *b == b'\t')
+}
+
+/// Parse the code for a single node, without the node tag.
+pub fn program_items(input: &[u8]) -> Result<Vec<ProgramItem>, Vec<ParseError>> {
+    let mut items = vec![];
+    let mut errors = vec![];
+    node_items(input, input, None, &mut items, &mut errors);
+    if errors.is_empty() {
+        Ok(items)
+    } else {
+        Err(errors)
     }
 }
 
 pub type Nodes = BTreeMap<SaveFileNodeId, Vec<ProgramItem>>;
 
-pub fn parse_save_file(input: &[u8]) -> Result<Nodes, (&[u8], Nodes)> {
-    let result = fold_many1(
-        tuple((node_tag, many0(program_item), opt(end_of_line))),
-        Nodes::new,
-        |mut acc, (node_id, items, _)| {
-            acc.insert(node_id, items);
-            acc
+/// Parse as much of a save file as possible. Lines with errors are skipped, and the errors are
+/// returned along with whatever could be parsed.
+pub fn parse_save_file_partial(input: &[u8]) -> (Nodes, Vec<ParseError>) {
+    let mut nodes = Nodes::new();
+    let mut errors = vec![];
+    let mut rest = input;
+    loop {
+        let (next, ()) = comments_and_whitespace(rest).expect("can't fail");
+        if next.is_empty() {
+            break;
+        }
+        match node_tag(next) {
+            Ok((next, id)) => {
+                let items = nodes.entry(id).or_default();
+                rest = node_items(input, next, Some(id), items, &mut errors);
+            }
+            Err(_) => {
+                errors.push(ParseError::new(input, next, None, "node tag like @0"));
+                rest = next_line(next);
+            }
+        }
+    }
+    (nodes, errors)
+}
+
+/// Parse a save file, returning all the errors in it if there are any.
+pub fn parse_save_file(input: &[u8]) -> Result<Nodes, Vec<ParseError>> {
+    let (nodes, errors) = parse_save_file_partial(input);
+    if errors.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Lay out a node's program as lines of code the way the game writes them: a label goes on the
+/// same line as the instruction after it, and a breakpoint goes at the start of its line.
+pub fn format_node(items: &[ProgramItem]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut breakpoint = false;
+    let mut label: Option<&str> = None;
+    for item in items {
+        match item {
+            ProgramItem::Breakpoint => {
+                if let Some(l) = label.take() {
+                    // a breakpoint is read before the label on the same line
+                    lines.push(format!("{}{}:", if breakpoint { "!" } else { "" }, l));
+                }
+                breakpoint = true;
+            }
+            ProgramItem::Label(l) => {
+                if let Some(prev) = label.replace(l) {
+                    // only one label fits on a line
+                    lines.push(format!("{}{}:", if breakpoint { "!" } else { "" }, prev));
+                    breakpoint = false;
+                }
+            }
+            ProgramItem::Instruction(i) => {
+                let mut line = String::new();
+                if breakpoint {
+                    line.push('!');
+                }
+                if let Some(l) = label.take() {
+                    line += &format!("{}: ", l);
+                }
+                line += &i.to_string();
+                lines.push(line);
+                breakpoint = false;
+            }
+        }
+    }
+    match (breakpoint, label) {
+        (_, Some(l)) => lines.push(format!("{}{}:", if breakpoint { "!" } else { "" }, l)),
+        (true, None) => lines.push("!".to_owned()),
+        (false, None) => (),
+    }
+    lines
+}
+
+/// Write nodes out as the text of a save file which the game can load. Nodes don't keep comments,
+/// blank lines or spacing, so those are lost; `syntax::SourceFile::format` rewrites a file while
+/// keeping them.
+pub fn write_save_file(nodes: &Nodes) -> String {
+    let mut out = String::new();
+    for (id, items) in nodes {
+        out += &format!("@{}\n", id.0);
+        for line in format_node(items) {
+            out += &line;
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Maximum number of lines of code a T21 node can hold.
+pub const MAX_LINES: usize = 15;
+
+/// Maximum number of characters in a line of T21 code.
+pub const MAX_LINE_LENGTH: usize = 18;
+
+/// A place where a node's program is bigger than what fits in a T21 node in the game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeViolation {
+    pub node: SaveFileNodeId,
+
+    /// Line number in the save file, starting at 1.
+    pub line: usize,
+
+    /// Column of the first character over the limit, starting at 1.
+    pub column: usize,
+
+    pub kind: SizeViolationKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SizeViolationKind {
+    /// The node has this many lines, which is more than MAX_LINES.
+    TooManyLines(usize),
+
+    /// The line is this many characters long, which is more than MAX_LINE_LENGTH.
+    LineTooLong(usize),
+}
+
+impl Display for SizeViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: node @{}: ", self.line, self.column, self.node.0)?;
+        match self.kind {
+            SizeViolationKind::TooManyLines(n) => {
+                write!(f, "{} lines is more than the limit of {}", n, MAX_LINES)
+            }
+            SizeViolationKind::LineTooLong(n) => {
+                write!(f, "line of {} characters is longer than the limit of {}",
+                    n, MAX_LINE_LENGTH)
+            }
         }
-    )(input);
+    }
+}
+
+/// Check that each node's code in a save file would fit in a real T21 node. Comments, labels and
+/// blank lines count against the limits, like in the game, except for blank lines at the end of a
+/// node.
+pub fn check_size_limits(input: &[u8]) -> Vec<SizeViolation> {
+    let mut violations = vec![];
 
-    match result {
-        Ok((&[], map)) => Ok(map),
-        Ok((rest, map)) => {
-            error!("Parse error: not all input processed");
-            Err((rest, map))
+    // (node id, save file line number of each line in the node, with the length of the line)
+    let mut current: Option<(SaveFileNodeId, Vec<(usize, usize)>)> = None;
+
+    let mut finish_node = |node: Option<(SaveFileNodeId, Vec<(usize, usize)>)>| {
+        if let Some((id, mut lines)) = node {
+            while let Some((_, 0)) = lines.last() {
+                lines.pop();
+            }
+            for (line, len) in &lines {
+                if *len > MAX_LINE_LENGTH {
+                    violations.push(SizeViolation {
+                        node: id,
+                        line: *line,
+                        column: MAX_LINE_LENGTH + 1,
+                        kind: SizeViolationKind::LineTooLong(*len),
+                    });
+                }
+            }
+            if lines.len() > MAX_LINES {
+                violations.push(SizeViolation {
+                    node: id,
+                    line: lines[MAX_LINES].0,
+                    column: 1,
+                    kind: SizeViolationKind::TooManyLines(lines.len()),
+                });
+            }
         }
-        Err(nom::Err::Incomplete(_needed)) => unreachable!("incomplete parse should not be possible when using 'complete' parser"),
-        Err(nom::Err::Error(err_kind)) | Err(nom::Err::Failure(err_kind)) => {
-            error!("Parse error: {:?}", err_kind);
-            Err(Default::default())
+    };
+
+    for (idx, line) in input.split(|b| *b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let indent = line.iter().take_while(|b| **b == b' ' || **b == b'\t').count();
+        if let Ok((_, id)) = node_tag(&[&line[indent ..], b"\n"].concat()) {
+            finish_node(current.take());
+            current = Some((id, vec![]));
+        } else if let Some((_, lines)) = &mut current {
+            let len = String::from_utf8_lossy(line).chars().count();
+            lines.push((idx + 1, len));
         }
     }
+    finish_node(current);
+
+    violations.sort_by_key(|v| v.line);
+    violations
 }
 
 #[cfg(test)]
@@ -329,6 +640,13 @@ mod tests {
     #[test]
     fn test_immediate() {
         assert_eq!(-325, immediate(b"-325").unwrap().1);
+        assert!(matches!(immediate(b"1000"), Err(nom::Err::Failure(_))));
+        assert_eq!(
+            "1:5: expected number from -999 to 999, found \"1000\"",
+            program_items(b"ADD 1000").unwrap_err()[0].to_string());
+        assert_eq!(
+            "1:5: expected number from -999 to 999, found \"-99999\"",
+            program_items(b"MOV -99999, ACC").unwrap_err()[0].to_string());
     }
 
     #[test]
@@ -345,4 +663,179 @@ mod tests {
         check!(arg_sep, b",   ");
         check!(arg_sep, b"  ,  ");
     }
+
+    #[test]
+    fn test_size_limits() {
+        assert_eq!(Vec::<SizeViolation>::new(),
+            check_size_limits(b"@0\nMOV UP, DOWN\n\n\n@1\nMOV ANY, ACC #2345\n"));
+
+        let long_line = b"@0\nNOP\n@1\nNOP\r\nMOV ANY, ACC #234567\r\n";
+        assert_eq!(
+            vec![SizeViolation {
+                node: SaveFileNodeId(1),
+                line: 5,
+                column: 19,
+                kind: SizeViolationKind::LineTooLong(20),
+            }],
+            check_size_limits(long_line));
+
+        let mut many_lines = b"@0\n".to_vec();
+        for _ in 0 .. 16 {
+            many_lines.extend_from_slice(b"# just a comment\n");
+        }
+        many_lines.extend_from_slice(b"\n\n@1\n");
+        assert_eq!(
+            vec![SizeViolation {
+                node: SaveFileNodeId(0),
+                line: 17,
+                column: 1,
+                kind: SizeViolationKind::TooManyLines(16),
+            }],
+            check_size_limits(&many_lines));
+
+        // an indented tag still starts a new node
+        let mut indented = b"@0\nNOP\n  @1\n".to_vec();
+        for _ in 0 .. 16 {
+            indented.extend_from_slice(b"NOP\n");
+        }
+        assert_eq!(
+            vec![SizeViolation {
+                node: SaveFileNodeId(1),
+                line: 19,
+                column: 1,
+                kind: SizeViolationKind::TooManyLines(16),
+            }],
+            check_size_limits(&indented));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let input = b"\
+@0
+MOV UP, DOWN
+MOV UP DOWN
+MOV FOO, ACC
+
+@1
+L: ADD 1000
+JMP # nowhere
+SWP garbage
+NEG,
+";
+        let err = |line, column, node, text: &str, expected| ParseError {
+            node: Some(SaveFileNodeId(node)),
+            line,
+            column,
+            text: text.to_owned(),
+            expected,
+        };
+        let (nodes, errors) = parse_save_file_partial(input);
+        assert_eq!(
+            vec![
+                err(4, 5, 0, "FOO", "port, register or number after MOV"),
+                err(7, 8, 1, "1000", "number from -999 to 999"),
+                err(8, 5, 1, "#", "label after JMP"),
+                err(9, 5, 1, "garbage", "end of line or comment"),
+                err(10, 4, 1, ",", "end of line or comment"),
+            ],
+            errors);
+
+        // The lines with errors are skipped.
+        assert_eq!(
+            btreemap! {
+                SaveFileNodeId(0) => vec![
+                    ProgramItem::Instruction(Instruction::MOV(Src::Port(Port::UP), Dst::Port(Port::DOWN))),
+                    ProgramItem::Instruction(Instruction::MOV(Src::Port(Port::UP), Dst::Port(Port::DOWN))),
+                ],
+                SaveFileNodeId(1) => vec![ProgramItem::Label("L".to_owned())],
+            },
+            nodes);
+
+        assert_eq!(
+            "4:5: node @0: expected port, register or number after MOV, found \"FOO\"",
+            errors[0].to_string());
+    }
+
+    #[test]
+    fn test_parse_error_outside_node() {
+        assert_eq!(
+            Err(vec![ParseError {
+                node: None,
+                line: 2,
+                column: 1,
+                text: "NOP".to_owned(),
+                expected: "node tag like @0",
+            }]),
+            parse_save_file(b"# leading comment\nNOP\n@0\nNOP\n"));
+    }
+
+    #[test]
+    fn test_missing_operand() {
+        assert_eq!(
+            "1:8: expected port or register after MOV's source, found end of line",
+            program_items(b"MOV UP,\nNOP").unwrap_err()[0].to_string());
+        assert_eq!(
+            "1:1: expected instruction, label or breakpoint, found \"FOO\"",
+            program_items(b"FOO BAR").unwrap_err()[0].to_string());
+    }
+
+    #[test]
+    fn test_format_node() {
+        let items = program_items(b"\
+A:
+B: MOV UP, ACC
+!C:
+!JRO -1 # comment
+  !
+D:").unwrap();
+        assert_eq!(
+            vec!["A:", "B: MOV UP, ACC", "!C:", "!JRO -1", "!D:"],
+            format_node(&items));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = b"\
+@0
+MOV UP, DOWN
+@1
+# comment
+LOOP:MOV 5,ACC
+!  ADD LEFT
+ SUB -3 # another
+  SWP
+!A: SAV
+NEG
+B: C:
+JEZ LOOP
+JMP B
+JNZ C
+JGZ A
+JLZ LOOP
+JRO ANY
+MOV ACC LAST
+HCF
+NOP
+@2
+
+@3
+END:
+";
+        let nodes = parse_save_file(input).unwrap();
+        let text = write_save_file(&nodes);
+        assert_eq!(nodes, parse_save_file(text.as_bytes()).unwrap());
+        assert!(text.starts_with("@0\nMOV UP, DOWN\n\n@1\nLOOP: MOV 5, ACC\n!ADD LEFT\nSUB -3\n"));
+        assert!(text.ends_with("\n@2\n\n@3\nEND:\n\n"));
+
+        // and formatting is stable
+        assert_eq!(text, write_save_file(&parse_save_file(text.as_bytes()).unwrap()));
+
+        // a breakpoint after a label can't go on the label's line
+        for input in [&b"@0\nA:\n!NOP\n"[..], b"@0\nA:\n!\nNOP\n", b"@0\n!A:\n!NOP\n"] {
+            let nodes = parse_save_file(input).unwrap();
+            let text = write_save_file(&nodes);
+            assert_eq!(nodes, parse_save_file(text.as_bytes()).unwrap(), "{}", text);
+        }
+        assert_eq!("@0\nA:\n!NOP\n\n", write_save_file(&parse_save_file(b"@0\nA:\n!NOP").unwrap()));
+    }
 }
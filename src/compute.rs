@@ -1,12 +1,17 @@
+use crate::error::{Error, ErrorKind};
 use crate::instr::*;
 use crate::node::{StepResult, ReadResult, ComputeResult, WriteResult, AdvanceResult, NodeOps};
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComputeNode {
     pub instructions: Vec<Instruction>,
+    /// The instructions with their jumps resolved, which is what actually runs.
+    pub ops: Vec<Op>,
     pub labels: HashMap<String, usize>,
+    pub breakpoints: BTreeSet<usize>, // instruction indices
     pub acc: i32,
     pub bak: i32,
     pub pc: usize,
@@ -19,12 +24,20 @@ pub enum CycleStep {
     Read, Compute, Write, Advance,
 }
 
+/// Values in a node's registers are limited to this range; arithmetic saturates at the ends.
+pub const MIN_VALUE: i32 = -999;
+pub const MAX_VALUE: i32 = 999;
+
+fn saturate(value: i32) -> i32 {
+    value.clamp(MIN_VALUE, MAX_VALUE)
+}
+
 macro_rules! get_instr {
     ($self:expr) => {
-        match $self.instructions.get($self.pc) {
-            Some(i) => i,
+        match $self.ops.get($self.pc) {
+            Some(op) => *op,
             None => {
-                return StepResult::Nothing;
+                return Ok(StepResult::Nothing);
             }
         }
     };
@@ -34,7 +47,9 @@ impl Default for ComputeNode {
     fn default() -> Self {
         ComputeNode {
             instructions: vec![],
+            ops: vec![],
             labels: HashMap::new(),
+            breakpoints: BTreeSet::new(),
             acc: 0,
             bak: 0,
             pc: 0,
@@ -45,36 +60,98 @@ impl Default for ComputeNode {
 }
 
 impl ComputeNode {
-    pub fn load_assembly(&mut self, items: impl Iterator<Item=ProgramItem>) {
+    pub fn load_assembly(&mut self, items: impl Iterator<Item=ProgramItem>) -> Result<(), Error> {
+        let mut instructions = vec![];
+        let mut labels = HashMap::new();
+        let mut breakpoints = BTreeSet::new();
         for item in items {
             match item {
                 ProgramItem::Instruction(i) => {
-                    self.instructions.push(i);
+                    instructions.push(i);
                 }
                 ProgramItem::Label(s) => {
-                    self.labels.insert(s, self.instructions.len());
+                    if labels.contains_key(&s) {
+                        return Err(ErrorKind::DuplicateLabel(s).into());
+                    }
+                    labels.insert(s, instructions.len());
+                }
+                ProgramItem::Breakpoint => {
+                    // applies to the instruction that follows it
+                    breakpoints.insert(instructions.len());
                 }
-                ProgramItem::Breakpoint => (), // TODO
             }
         }
 
-        for instr in &self.instructions {
-            match instr {
-                Instruction::JMP(l) | Instruction::JEZ(l) | Instruction::JNZ(l)
-                    | Instruction::JGZ(l) | Instruction::JLZ(l) =>
-                {
-                    if !self.labels.contains_key(l) {
-                        panic!("instruction references undefined label {:?}", l);
-                    }
-                },
-                _ => ()
+        let ops = instructions.iter()
+            .enumerate()
+            .map(|(pc, instr)| instr.link(&labels)
+                .map_err(|l| Error::new(ErrorKind::UndefinedLabel(l.to_owned()))
+                    .at_instruction(pc, instr)))
+            .collect::<Result<Vec<Op>, Error>>()?;
+
+        self.instructions = instructions;
+        self.ops = ops;
+        self.labels = labels;
+        self.breakpoints = breakpoints;
+        Ok(())
+    }
+
+    /// Check that the program counter and jump targets are all inside the program.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.ops.len() != self.instructions.len() {
+            return Err(format!("{} ops for {} instructions", self.ops.len(),
+                self.instructions.len()));
+        }
+        if self.pc >= self.ops.len().max(1) {
+            return Err(format!("pc {} is past the end of {} instructions", self.pc,
+                self.ops.len()));
+        }
+        for op in &self.ops {
+            if let Op::JMP(target) | Op::JEZ(target) | Op::JNZ(target) | Op::JGZ(target)
+                | Op::JLZ(target) = op
+            {
+                if *target >= self.ops.len() {
+                    return Err(format!("jump to {} is past the end of {} instructions", target,
+                        self.ops.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Make an error for the current instruction.
+    fn error(&self, kind: ErrorKind) -> Error {
+        let err = Error::new(kind);
+        match self.instructions.get(self.pc) {
+            Some(instr) => err.at_instruction(self.pc, instr),
+            None => err,
+        }
+    }
+
+    fn resolve_last<const LOG: bool>(&self, port: Port) -> Result<Port, Error> {
+        if port == Port::LAST {
+            if self.last == Port::LAST {
+                return Err(self.error(ErrorKind::UnsetLastPort));
             }
+            log_if!(LOG, trace, "LAST -> {}", self.last);
+            Ok(self.last)
+        } else {
+            Ok(port)
+        }
+    }
+
+    /// The port the current instruction reads from, if it reads from one. LAST is resolved to the
+    /// actual port if it has been set.
+    pub fn read_port(&self) -> Option<Port> {
+        match self.ops.get(self.pc)?.src()? {
+            Src::Port(Port::LAST) if self.last != Port::LAST => Some(self.last),
+            Src::Port(port) => Some(*port),
+            _ => None,
         }
     }
 
     pub fn complete_write(&mut self, port: Port) {
-        if matches!(self.instructions.get(self.pc),
-            Some(Instruction::MOV(_src, Dst::Port(Port::ANY))))
+        if matches!(self.ops.get(self.pc), Some(Op::MOV(_src, Dst::Port(Port::ANY))))
         {
             self.last = port;
         }
@@ -82,25 +159,24 @@ impl ComputeNode {
 }
 
 impl NodeOps for ComputeNode {
-    fn read(&mut self, avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
-        let instr = get_instr!(self);
-        trace!("{}", instr);
-
-        let src = match instr {
-            Instruction::MOV(src, _dst) => src,
-            Instruction::ADD(src) => src,
-            Instruction::SUB(src) => src,
-            Instruction::JRO(src) => src,
-            _ => {
-                trace!("no read needed");
-                return StepResult::Nothing;
+    fn read<const LOG: bool>(&mut self, avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
+        let op = get_instr!(self);
+        log_if!(LOG, trace, "{}", self.instructions[self.pc]);
+
+        let src = match op.src() {
+            Some(src) => src,
+            None => {
+                log_if!(LOG, trace, "no read needed");
+                return Ok(StepResult::Nothing);
             }
         };
 
-        self.read_result = Some(match src {
+        self.read_result = Some(match *src {
             Src::Register(Register::ACC) => self.acc,
             Src::Register(Register::NIL) => 0,
-            Src::Immediate(value) => i32::from(*value),
+            Src::Immediate(value) => i32::from(value),
             Src::Port(port) => {
                 fn read(
                     port: Port,
@@ -118,112 +194,93 @@ impl NodeOps for ComputeNode {
                     None
                 }
 
-                let actual_port = if *port == Port::LAST {
-                    if self.last == Port::LAST {
-                        panic!("attempted to read from unset LAST port!");
-                    }
-                    trace!("LAST -> {}", self.last);
-                    self.last
-                } else {
-                    *port
-                };
+                let actual_port = self.resolve_last::<LOG>(port)?;
 
                 match read(actual_port, avail_reads, &mut self.last) {
                     Some(value) => {
-                        trace!("ready value from {}: {}", actual_port, value);
+                        log_if!(LOG, trace, "ready value from {}: {}", actual_port, value);
                         value
                     }
                     None => {
-                        trace!("waiting for {}", *port);
-                        return StepResult::IO(*port);
+                        log_if!(LOG, trace, "waiting for {}", port);
+                        return Ok(StepResult::IO(port));
                     }
                 }
             }
         });
 
-        StepResult::Okay
+        Ok(StepResult::Okay)
     }
 
-    fn compute(&mut self) -> ComputeResult {
+    fn compute<const LOG: bool>(&mut self) -> Result<ComputeResult, Error> {
         match get_instr!(self) {
-            Instruction::NOP => (),
-            Instruction::MOV(_src, _dst) => (),
-            Instruction::SWP => {
+            Op::NOP => (),
+            Op::MOV(_src, _dst) => (),
+            Op::SWP => {
                 std::mem::swap(&mut self.acc, &mut self.bak);
             }
-            Instruction::SAV => {
+            Op::SAV => {
                 self.bak = self.acc;
             }
-            Instruction::ADD(_src) => {
-                self.acc += self.read_result.unwrap();
+            Op::ADD(_src) => {
+                self.acc = saturate(self.acc + self.read_result.unwrap());
             }
-            Instruction::SUB(_src) => {
-                self.acc -= self.read_result.unwrap();
+            Op::SUB(_src) => {
+                self.acc = saturate(self.acc - self.read_result.unwrap());
             }
-            Instruction::NEG => {
-                self.acc = -self.acc;
+            Op::NEG => {
+                self.acc = saturate(-self.acc);
             }
-            Instruction::JMP(_) | Instruction::JEZ(_) | Instruction::JNZ(_) | Instruction::JGZ(_)
-                | Instruction::JLZ(_) | Instruction::JRO(_) => (),
-            Instruction::HCF => {
-                error!("tried to execute a Halt and Catch Fire instruction");
-                error!("current node state is {:#?}", self);
-                error!("we are panicking now");
-                panic!("HCF");
+            Op::JMP(_) | Op::JEZ(_) | Op::JNZ(_) | Op::JGZ(_) | Op::JLZ(_) | Op::JRO(_) => (),
+            Op::HCF => {
+                log_if!(LOG, debug, "tried to execute a Halt and Catch Fire instruction");
+                log_if!(LOG, debug, "current node state is {:#?}", self);
+                return Err(self.error(ErrorKind::HaltAndCatchFire));
             }
         }
 
-        StepResult::Okay
+        Ok(StepResult::Okay)
     }
 
-    fn write(&mut self) -> WriteResult {
-        let instr = get_instr!(self);
-        trace!("{}", instr);
+    fn write<const LOG: bool>(&mut self) -> Result<WriteResult, Error> {
+        let op = get_instr!(self);
+        log_if!(LOG, trace, "{}", self.instructions[self.pc]);
 
-        if let Instruction::MOV(_src, dst) = instr {
+        if let Op::MOV(_src, dst) = op {
             let val = self.read_result.unwrap();
             match dst {
                 Dst::Register(Register::ACC) => { self.acc = val; }
                 Dst::Register(Register::NIL) => (),
                 Dst::Port(port) => {
-                    let actual_port = if *port == Port::LAST {
-                        if self.last == Port::LAST {
-                            panic!("attempted to write to unset LAST port");
-                        }
-                        trace!("LAST -> {}", self.last);
-                        self.last
-                    } else {
-                        *port
-                    };
-
-                    trace!("writing {} to {}", val, actual_port);
-                    return StepResult::IO((actual_port, val));
+                    let actual_port = self.resolve_last::<LOG>(port)?;
+                    log_if!(LOG, trace, "writing {} to {}", val, actual_port);
+                    return Ok(StepResult::IO((actual_port, val)));
                 }
             }
-            StepResult::Okay
+            Ok(StepResult::Okay)
         } else {
-            trace!("no write needed");
-            StepResult::Nothing
+            log_if!(LOG, trace, "no write needed");
+            Ok(StepResult::Nothing)
         }
     }
 
-    fn advance(&mut self) -> AdvanceResult {
-        let instr = get_instr!(self);
+    fn advance<const LOG: bool>(&mut self) -> Result<AdvanceResult, Error> {
+        let op = get_instr!(self);
 
-        match instr {
-            Instruction::JRO(_) => (),
+        match op {
+            Op::JRO(_) => (),
             _ => {
                 self.pc += 1;
             }
         }
 
-        match instr {
-            Instruction::JMP(label) => { self.pc = self.labels[label]; }
-            Instruction::JEZ(label) => if self.acc == 0 { self.pc = self.labels[label]; }
-            Instruction::JNZ(label) => if self.acc != 0 { self.pc = self.labels[label]; }
-            Instruction::JGZ(label) => if self.acc > 0 { self.pc = self.labels[label]; }
-            Instruction::JLZ(label) => if self.acc < 0 { self.pc = self.labels[label]; }
-            Instruction::JRO(_src) => {
+        match op {
+            Op::JMP(target) => { self.pc = target; }
+            Op::JEZ(target) if self.acc == 0 => { self.pc = target; }
+            Op::JNZ(target) if self.acc != 0 => { self.pc = target; }
+            Op::JGZ(target) if self.acc > 0 => { self.pc = target; }
+            Op::JLZ(target) if self.acc < 0 => { self.pc = target; }
+            Op::JRO(_src) => {
                 let off = self.read_result.unwrap();
                 if off < 0 {
                     if (-off) as usize > self.pc {
@@ -237,19 +294,85 @@ impl NodeOps for ComputeNode {
 
                 // As an exception to the normal wrap-around, a JRO out of bounds goes to the
                 // last instruction.
-                if self.pc >= self.instructions.len() {
-                    self.pc = self.instructions.len() - 1;
+                if self.pc >= self.ops.len() {
+                    self.pc = self.ops.len() - 1;
                 }
             }
             _ => ()
         }
 
-        if self.pc >= self.instructions.len() {
+        if self.pc >= self.ops.len() {
             self.pc = 0;
         }
 
         self.read_result = None;
 
-        StepResult::Okay
+        Ok(StepResult::Okay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a node with no neighbors until it has executed the given number of instructions.
+    fn run(asm: &str, instructions: usize) -> ComputeNode {
+        let mut node = ComputeNode::default();
+        node.load_assembly(crate::assembly::program_items(asm.as_bytes()).unwrap().into_iter())
+            .unwrap();
+        for _ in 0 .. instructions {
+            node.read::<true>(&mut []).unwrap();
+            node.compute::<true>().unwrap();
+            node.write::<true>().unwrap();
+            node.advance::<true>().unwrap();
+        }
+        node
+    }
+
+    #[test]
+    fn test_add_saturates() {
+        assert_eq!(999, run("ADD 999\nADD 1", 2).acc);
+        assert_eq!(999, run("ADD 999\nADD 999", 2).acc);
+        assert_eq!(998, run("ADD 999\nADD 999\nADD -1", 3).acc);
+        assert_eq!(-999, run("ADD -999\nADD -999", 2).acc);
+    }
+
+    #[test]
+    fn test_sub_saturates() {
+        assert_eq!(-999, run("SUB 999\nSUB 1", 2).acc);
+        assert_eq!(-999, run("SUB 999\nSUB 999", 2).acc);
+        assert_eq!(-998, run("SUB 999\nSUB 999\nSUB -1", 3).acc);
+        assert_eq!(999, run("SUB -999\nSUB -999", 2).acc);
+    }
+
+    #[test]
+    fn test_neg_at_limits() {
+        assert_eq!(-999, run("ADD 999\nNEG", 2).acc);
+        assert_eq!(999, run("SUB 999\nNEG", 2).acc);
+        assert_eq!(999, run("ADD 999\nADD 999\nNEG\nNEG", 4).acc);
+    }
+
+    #[test]
+    fn test_saturated_value_swaps() {
+        let node = run("ADD 999\nADD 999\nSAV\nSUB 999\nSUB 999\nSUB 999\nSWP", 7);
+        assert_eq!(999, node.acc);
+        assert_eq!(-999, node.bak);
+    }
+
+    #[test]
+    fn test_jumps_linked() {
+        let node = run("START: ADD 1\nJGZ END\nJMP START\nEND: SUB 1\nJEZ START", 0);
+        assert_eq!(
+            vec![
+                Op::ADD(Src::Immediate(1)),
+                Op::JGZ(3),
+                Op::JMP(0),
+                Op::SUB(Src::Immediate(1)),
+                Op::JEZ(0),
+            ],
+            node.ops);
+
+        // ADD, JGZ, SUB, JEZ, ADD
+        assert_eq!(1, run("START: ADD 1\nJGZ END\nJMP START\nEND: SUB 1\nJEZ START", 5).acc);
     }
 }
@@ -0,0 +1,321 @@
+use crate::error::Error;
+use crate::grid::{ComputeGrid, Outcome, StopReason};
+use crate::history::{Found, History};
+use crate::instr::Port;
+use crate::node::{CycleStep, NodeType};
+use crate::snapshot::Snapshot;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+const HELP: &str = "\
+commands:
+  s, step [N]      run N cycles (default 1), stopping early at a breakpoint
+  p, phase         run the next phase of the current cycle
+  c, continue      run until a breakpoint or the end of the run
+  u, until CYCLE   run until the given cycle has finished, or a breakpoint, or go back to it
+  b, back [N]      go back N cycles (default 1)
+  lw, lastwrite NODE PORT
+                   go back to the start of the last cycle NODE sent a value out of PORT
+                   (UP, DOWN, LEFT, RIGHT, or ANY for any of them)
+  la, lastacc NODE go back to the start of the last cycle NODE's ACC changed in
+  i, inspect NODE  show everything about a node
+  g, grid          show the grid again
+  save FILE        write the whole state of the grid to a file
+  load FILE        go back to the state saved in a file
+  q, quit          exit the debugger
+  h, help          show this message
+an empty line repeats the last command";
+
+enum Command {
+    Step(usize),
+    Phase,
+    Continue,
+    Until(usize),
+    Back(usize),
+    LastWrite(usize, Port),
+    LastAcc(usize),
+    Inspect(usize),
+    Grid,
+    Save(String),
+    Load(String),
+    Quit,
+    Help,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let mut number = |what: &str| -> Result<Option<usize>, String> {
+        words.next()
+            .map(|word| word.parse::<usize>().map_err(|_| format!("bad {}: {:?}", what, word)))
+            .transpose()
+    };
+    Ok(match cmd {
+        "s" | "step" => Command::Step(number("count")?.unwrap_or(1)),
+        "p" | "phase" => Command::Phase,
+        "c" | "continue" => Command::Continue,
+        "u" | "until" => Command::Until(number("cycle")?.ok_or("until needs a cycle number")?),
+        "b" | "back" => Command::Back(number("count")?.unwrap_or(1)),
+        "lw" | "lastwrite" => {
+            let node = number("node")?.ok_or("lastwrite needs a node number and a port")?;
+            let port = match words.next().map(str::to_uppercase).as_deref() {
+                Some("UP") => Port::UP,
+                Some("DOWN") => Port::DOWN,
+                Some("LEFT") => Port::LEFT,
+                Some("RIGHT") => Port::RIGHT,
+                Some("ANY") => Port::ANY,
+                Some(word) => return Err(format!("bad port: {:?}", word)),
+                None => return Err("lastwrite needs a port".to_owned()),
+            };
+            Command::LastWrite(node, port)
+        }
+        "la" | "lastacc" => Command::LastAcc(number("node")?.ok_or("lastacc needs a node number")?),
+        "i" | "inspect" => Command::Inspect(number("node")?.ok_or("inspect needs a node number")?),
+        "g" | "grid" => Command::Grid,
+        "save" => Command::Save(words.next().ok_or("save needs a file name")?.to_owned()),
+        "load" => Command::Load(words.next().ok_or("load needs a file name")?.to_owned()),
+        "q" | "quit" => Command::Quit,
+        "h" | "help" | "?" => Command::Help,
+        _ => return Err(format!("unknown command {:?}; try \"help\"", cmd)),
+    })
+}
+
+fn show(grid: &ComputeGrid) {
+    grid.print();
+    grid.print_io();
+    println!("cycle {}, next phase {}", grid.cycle() + 1, grid.phase());
+}
+
+fn inspect(grid: &ComputeGrid, idx: usize) {
+    let node = match grid.node(idx) {
+        Some(node) => node,
+        None => {
+            println!("no node {}", idx);
+            return;
+        }
+    };
+
+    println!("node {}: {} node, mode {}", idx, node.type_name(), node.step);
+    if let Some((port, value)) = node.pending_output {
+        println!("pending output: {} to {}", value, port);
+    }
+
+    match &node.inner {
+        NodeType::Compute(c) => {
+            println!("ACC {}  BAK {}  LAST {}", c.acc, c.bak, c.last);
+            println!("{}", grid.node_stats()[idx]);
+            if let Some(value) = c.read_result {
+                println!("value read: {}", value);
+            }
+            for (i, instr) in c.instructions.iter().enumerate() {
+                let labels = c.labels.iter()
+                    .filter(|(_label, target)| **target == i)
+                    .map(|(label, _target)| format!("{}:", label))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                println!("{}{}{:3} {:10} {}",
+                    if c.pc == i { '>' } else { ' ' },
+                    if c.breakpoints.contains(&i) { '!' } else { ' ' },
+                    i,
+                    labels,
+                    instr);
+            }
+        }
+        NodeType::Stack(stack) => {
+            println!("values (bottom to top): {:?}", stack.values());
+        }
+        _ => (),
+    }
+}
+
+/// Print the reason a command stopped. Returns whether the grid can keep running forward, which it
+/// can't after an error.
+fn report(grid: &ComputeGrid, result: Result<StopReason, Error>) -> bool {
+    match result {
+        Ok(StopReason::Stepped) => {
+            show(grid);
+            true
+        }
+        Ok(StopReason::Breakpoint(hits)) => {
+            show(grid);
+            for hit in hits {
+                println!("{}", hit);
+            }
+            true
+        }
+        Ok(StopReason::Finished(outcome)) => {
+            show(grid);
+            println!("run finished after {} cycles: {}", grid.cycle(), outcome);
+            match outcome {
+                Outcome::Deadlock(blocked) => {
+                    for node in blocked {
+                        println!("  {}", node);
+                    }
+                }
+                Outcome::Incorrect(Some(mismatch)) => {
+                    println!("{}", mismatch);
+                    print!("{}", mismatch.side_by_side());
+                }
+                _ => (),
+            }
+            true
+        }
+        Err(e) => {
+            println!("error in cycle {}: {}", grid.cycle() + 1, e);
+            false
+        }
+    }
+}
+
+/// Interactively step through a run of the grid, reading commands from stdin.
+pub fn run(grid: &mut ComputeGrid) -> io::Result<()> {
+    show(grid);
+    println!("{}", HELP);
+
+    let stdin = io::stdin();
+    let mut last_line = String::new();
+    let mut history = History::new(grid);
+    let mut can_run = true; // false once an error has stopped the run, until going back
+    loop {
+        print!("(tis100) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            // end of input
+            println!();
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            line = last_line.clone();
+        }
+
+        let cmd = match parse_command(&line) {
+            Ok(cmd) => cmd,
+            Err(msg) => {
+                println!("{}", msg);
+                continue;
+            }
+        };
+        last_line = line;
+
+        let mid_cycle = grid.phase() != CycleStep::Read;
+        let forward = match cmd {
+            Command::Step(_) | Command::Phase | Command::Continue => true,
+            Command::Until(cycle) => cycle > grid.cycle() || (cycle == grid.cycle() && !mid_cycle),
+            _ => false,
+        };
+        if forward && !can_run {
+            println!("the run stopped with an error; go back or quit");
+            continue;
+        }
+
+        let mut note = None;
+        let result = match cmd {
+            Command::Step(count) => history.run_until_breakpoint(grid, Some(count)),
+            Command::Phase => history.step_phase(grid),
+            Command::Continue => history.run_until_breakpoint(grid, None),
+            Command::Until(cycle) if cycle == grid.cycle() && !mid_cycle => {
+                println!("already at the end of cycle {}", cycle);
+                continue;
+            }
+            Command::Until(cycle) if cycle < history.start() => {
+                println!("can't go back past cycle {}", history.start());
+                continue;
+            }
+            Command::Until(cycle) if cycle <= grid.cycle() => {
+                history.rewind_to(grid, cycle).map(|()| StopReason::Stepped)
+            }
+            Command::Until(cycle) => {
+                let count = cycle - grid.cycle();
+                history.run_until_breakpoint(grid, Some(count))
+            }
+            Command::Back(count) => {
+                history.step_back(grid, count).map(|()| StopReason::Stepped)
+            }
+            Command::LastWrite(idx, port) => {
+                match history.last_write(grid, idx, port) {
+                    Ok(Some(found)) => {
+                        note = Some(found_text(grid, found));
+                        Ok(StopReason::Stepped)
+                    }
+                    Ok(None) => {
+                        println!("node {} sent nothing {} since cycle {}",
+                            idx, port, history.start());
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Command::LastAcc(idx) => {
+                match history.last_acc_change(grid, idx) {
+                    Ok(Some(found)) => {
+                        note = Some(found_text(grid, found));
+                        Ok(StopReason::Stepped)
+                    }
+                    Ok(None) => {
+                        println!("node {}'s ACC hasn't changed since cycle {}",
+                            idx, history.start());
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Command::Inspect(idx) => {
+                inspect(grid, idx);
+                continue;
+            }
+            Command::Grid => {
+                show(grid);
+                continue;
+            }
+            Command::Save(path) => {
+                let saved = File::create(&path)
+                    .and_then(|file| grid.snapshot().write(BufWriter::new(file)));
+                match saved {
+                    Ok(()) => println!("saved cycle {} to {}", grid.cycle(), path),
+                    Err(e) => println!("failed to save to {}: {}", path, e),
+                }
+                continue;
+            }
+            Command::Load(path) => {
+                let loaded = File::open(&path)
+                    .and_then(|file| Snapshot::read(BufReader::new(file)));
+                match loaded {
+                    Ok(snapshot) => {
+                        grid.restore(&snapshot);
+                        history = History::new(grid);
+                        can_run = true;
+                        show(grid);
+                    }
+                    Err(e) => println!("failed to load {}: {}", path, e),
+                }
+                continue;
+            }
+            Command::Quit => return Ok(()),
+            Command::Help => {
+                println!("{}", HELP);
+                continue;
+            }
+        };
+
+        can_run = report(grid, result);
+        if let Some(note) = note {
+            println!("{}", note);
+        }
+    }
+}
+
+/// Say what was found by going back, with the grid left at the start of the cycle it happens in.
+fn found_text(grid: &ComputeGrid, found: Found) -> String {
+    match found {
+        Found::Write { node, port, value } => {
+            format!("node {} sends {} {} in cycle {}", node, value, port, grid.cycle() + 1)
+        }
+        Found::Acc { node, from, to } => {
+            format!("node {}'s ACC changes from {} to {} in cycle {}",
+                node, from, to, grid.cycle() + 1)
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::grid::NodeLocation;
+use crate::instr::{Instruction, Port, SaveFileNodeId};
+use std::fmt::{self, Display, Formatter};
+
+/// An error which stops a program from being loaded or run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// The node the error happened in, if it is known.
+    pub location: Option<NodeLocation>,
+
+    /// Index of the instruction that caused the error, if any.
+    pub pc: Option<usize>,
+
+    /// The instruction that caused the error, if any.
+    pub instruction: Option<Instruction>,
+
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A jump instruction refers to a label which is not defined in the node.
+    UndefinedLabel(String),
+
+    /// A label is defined more than once in the node.
+    DuplicateLabel(String),
+
+    /// Tried to read from or write to LAST before any ANY read or write has set it.
+    UnsetLastPort,
+
+    /// Executed a HCF instruction.
+    HaltAndCatchFire,
+
+    /// A port was used somewhere it makes no sense, like getting the neighbor on the ANY port.
+    InvalidPort(Port),
+
+    /// Tried to load a program into a node which isn't a compute node.
+    NotProgrammable(&'static str),
+
+    /// The save file has a node which doesn't exist in the puzzle.
+    NoSuchNode(SaveFileNodeId),
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Error {
+            location: None,
+            pc: None,
+            instruction: None,
+            kind,
+        }
+    }
+
+    pub fn at_instruction(mut self, pc: usize, instruction: &Instruction) -> Self {
+        self.pc = Some(pc);
+        self.instruction = Some(instruction.clone());
+        self
+    }
+
+    pub fn at_node(mut self, location: NodeLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{}: ", location)?;
+        }
+        match (&self.pc, &self.instruction) {
+            (Some(pc), Some(instr)) => write!(f, "instruction {} ({}): ", pc, instr)?,
+            (Some(pc), None) => write!(f, "instruction {}: ", pc)?,
+            _ => (),
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UndefinedLabel(label) => write!(f, "undefined label {:?}", label),
+            ErrorKind::DuplicateLabel(label) => write!(f, "duplicate label {:?}", label),
+            ErrorKind::UnsetLastPort => f.write_str("LAST port used before it was set"),
+            ErrorKind::HaltAndCatchFire => f.write_str("halt and catch fire"),
+            ErrorKind::InvalidPort(port) => write!(f, "invalid use of port {}", port),
+            ErrorKind::NotProgrammable(type_name) => {
+                write!(f, "can't program a {} node", type_name)
+            }
+            ErrorKind::NoSuchNode(id) => write!(f, "no node @{} in this puzzle", id.0),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,19 +1,236 @@
 use crate::assembly::Nodes;
 use crate::compute::ComputeNode;
-use crate::instr::{Port, ProgramItem};
+use crate::error::{Error, ErrorKind};
+use crate::instr::{Port, ProgramItem, SaveFileNodeId};
 use crate::io::{InputNode, OutputNode, VerifyState};
-use crate::node::{Node, NodeType, NodeOps, BrokenNode, StepResult};
+use crate::node::{Node, NodeType, NodeOps, BrokenNode, StepResult, CycleStep, ProgressState};
+use crate::profile::InstructionProfile;
 use crate::puzzles::{Puzzle, PUZZLE_WIDTH, PUZZLE_HEIGHT, VIZ_WIDTH, VIZ_HEIGHT};
+use crate::snapshot::Snapshot;
 use crate::stack::StackNode;
 use crate::visualization::VisualizationNode;
 
 use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComputeGrid {
     nodes: Vec<Node>, // arranged in row-major order
+    #[serde(with = "crate::snapshot::pairs")]
     external: BTreeMap<(usize, Port), Node>,
     row_width: usize,
+    progress: Vec<ProgressState>, // state of each node (then each external node) after last step
+    transferred: bool, // whether any value was transferred during the current step
+    phase: CycleStep, // the next phase to run
+    cycle: usize, // number of completed cycles
+    breakpoint_hits: Vec<BreakpointHit>, // breakpoints reached during the current cycle
+    asleep: Vec<bool>, // compute nodes blocked on a read which no neighbor can satisfy yet
+    read_blocked: Vec<bool>, // compute nodes which couldn't read in the current cycle
+    stats: Vec<NodeStats>,
+    profile: Option<Vec<Vec<InstructionProfile>>>, // for each node, for each instruction
+    #[serde(with = "crate::snapshot::pairs")]
+    programmed: BTreeMap<SaveFileNodeId, usize>, // grid index of each node from the save file
+    transfers: Option<Vec<Transfer>>, // values passed between nodes, if they're being kept
+    skip_blocked: bool,
+}
+
+/// How a run of the grid ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every output node received all its expected values.
+    Correct,
+
+    /// An output node received a wrong value. Says which and where, unless it was an image that
+    /// was drawn wrong.
+    Incorrect(Option<Mismatch>),
+
+    /// A whole cycle went by without any node making progress, so the grid will never finish.
+    /// Lists the nodes that are stuck waiting on I/O.
+    Deadlock(Vec<BlockedNode>),
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Outcome::Correct => "correct",
+            Outcome::Incorrect(_) => "incorrect",
+            Outcome::Deadlock(_) => "deadlock",
+        })
+    }
+}
+
+/// Why a debugger command stopped running the grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// Ran as far as was asked.
+    Stepped,
+
+    /// One or more compute nodes reached an instruction with a breakpoint.
+    Breakpoint(Vec<BreakpointHit>),
+
+    /// The run ended.
+    Finished(Outcome),
+}
+
+/// A compute node which has reached an instruction marked with a breakpoint (`!`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakpointHit {
+    /// Index of the node in the grid.
+    pub node: usize,
+
+    /// Index of the instruction with the breakpoint.
+    pub pc: usize,
+}
+
+impl Display for BreakpointHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "node {} hit breakpoint at instruction {}", self.node, self.pc)
+    }
+}
+
+/// Get the profile of an instruction, making room for it if needed.
+fn instruction_profile(profile: &mut Vec<InstructionProfile>, pc: usize)
+    -> &mut InstructionProfile
+{
+    if profile.len() <= pc {
+        profile.resize(pc + 1, InstructionProfile::default());
+    }
+    &mut profile[pc]
+}
+
+/// How many cycles a compute node has spent doing each thing.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeStats {
+    /// Cycles spent running instructions.
+    pub executing: usize,
+
+    /// Cycles spent waiting for a value to read.
+    pub blocked_read: usize,
+
+    /// Cycles spent waiting for a written value to be taken.
+    pub blocked_write: usize,
+
+    /// Cycles with no program to run.
+    pub idle: usize,
+}
+
+impl NodeStats {
+    pub fn cycles(&self) -> usize {
+        self.executing + self.blocked_read + self.blocked_write + self.idle
+    }
+
+    /// Percentage of cycles not spent executing, like the IDLE figure the game shows.
+    pub fn idle_percent(&self) -> f64 {
+        match self.cycles() {
+            0 => 0.,
+            cycles => 100. * (cycles - self.executing) as f64 / cycles as f64,
+        }
+    }
+}
+
+impl Display for NodeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} executing, {} blocked on read, {} blocked on write, {} idle ({:.0}% IDLE)",
+            self.executing, self.blocked_read, self.blocked_write, self.idle, self.idle_percent())
+    }
+}
+
+/// Identifies a node attached to the grid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum NodeLocation {
+    /// A node in the grid, by its row-major index.
+    Grid(usize),
+
+    /// An I/O node attached to the given port of a grid node.
+    External(usize, Port),
+}
+
+impl Display for NodeLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.pad(&match self {
+            NodeLocation::Grid(idx) => format!("node {}", idx),
+            NodeLocation::External(idx, port) => format!("{} of node {}", port, idx),
+        })
+    }
+}
+
+/// A value passed from one node to another.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub from: NodeLocation,
+    pub to: NodeLocation,
+
+    /// The side of the `from` node the value left through.
+    pub port: Port,
+
+    pub value: i32,
+}
+
+/// A wrong value received by an output stream.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mismatch {
+    /// The grid node the output stream is attached to.
+    pub node: usize,
+
+    /// The side of the node it is attached to.
+    pub port: Port,
+
+    /// Index of the wrong value in the stream.
+    pub position: usize,
+
+    pub expected: i32,
+    pub received: i32,
+
+    /// The cycle the wrong value was received in.
+    pub cycle: usize,
+
+    /// Every value the stream expects.
+    pub expected_values: Vec<i32>,
+
+    /// The values received, ending with the wrong one.
+    pub received_values: Vec<i32>,
+}
+
+impl Mismatch {
+    /// The expected and received values next to each other, like the game's output panel, with
+    /// the wrong one marked.
+    pub fn side_by_side(&self) -> String {
+        let mut out = format!("{:>5} {:>8} {:>8}\n", "", "EXPECTED", "RECEIVED");
+        for (i, expected) in self.expected_values.iter().enumerate() {
+            let received = self.received_values.get(i).map(ToString::to_string)
+                .unwrap_or_default();
+            let marker = if i == self.position { "  <--" } else { "" };
+            let line = format!("{:5} {:8} {:>8}{}", i, expected, received, marker);
+            out += line.trim_end();
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} received {} at position {} in cycle {}, but expected {}",
+            NodeLocation::External(self.node, self.port), self.received, self.position, self.cycle,
+            self.expected)
+    }
+}
+
+/// A node which is stuck at some step of the cycle, waiting to read from or write to a port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockedNode {
+    pub location: NodeLocation,
+    pub type_name: &'static str,
+    pub step: CycleStep,
+    /// Port relative to the blocked node.
+    pub port: Port,
+}
+
+impl Display for BlockedNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: blocked on {} {}", self.type_name, self.location, self.step, self.port)
+    }
 }
 
 impl ComputeGrid {
@@ -49,16 +266,38 @@ impl ComputeGrid {
             nodes,
             external,
             row_width: PUZZLE_WIDTH,
+            progress: vec![],
+            transferred: false,
+            phase: CycleStep::Read,
+            cycle: 0,
+            breakpoint_hits: vec![],
+            asleep: vec![false; PUZZLE_WIDTH * PUZZLE_HEIGHT],
+            read_blocked: vec![false; PUZZLE_WIDTH * PUZZLE_HEIGHT],
+            stats: vec![NodeStats::default(); PUZZLE_WIDTH * PUZZLE_HEIGHT],
+            profile: None,
+            programmed: BTreeMap::new(),
+            transfers: None,
+            skip_blocked: true,
         }
     }
 
+    /// Choose whether nodes which can't do anything in a phase are skipped over, which is the
+    /// default. A node blocked on a read sleeps until a neighbor offers a new value, and a node
+    /// blocked on a write is left alone until its value is taken. Turning this off runs every node
+    /// in every phase, which is slower but gives a simple reference to check the scheduler against.
+    pub fn set_skip_blocked(&mut self, skip: bool) {
+        self.skip_blocked = skip;
+    }
+
     pub fn program_node(&mut self, idx: usize, program_items: impl IntoIterator<Item=ProgramItem>)
-        -> bool
+        -> Result<bool, Error>
     {
+        self.asleep[idx] = false;
         self.nodes[idx].program_node(program_items.into_iter())
+            .map_err(|e| e.at_node(NodeLocation::Grid(idx)))
     }
 
-    pub fn program_nodes(&mut self, nodes: Nodes) {
+    pub fn program_nodes(&mut self, nodes: Nodes) -> Result<(), Error> {
         let mut offset = 0;
         for (id, asm) in nodes {
             if log_enabled!(log::Level::Debug) {
@@ -71,9 +310,13 @@ impl ComputeGrid {
             let mut asm_iter = asm.into_iter();
             loop {
                 let idx = id.0 as usize + offset;
-                let programmed = self.program_node(idx, &mut asm_iter);
+                if idx >= self.nodes.len() {
+                    return Err(ErrorKind::NoSuchNode(id).into());
+                }
+                let programmed = self.program_node(idx, &mut asm_iter)?;
                 if programmed {
                     debug!("\tprogrammed node {}", idx);
+                    self.programmed.insert(id, idx);
                     break;
                 } else {
                     // encountered a broken node; continue to the next one.
@@ -81,6 +324,105 @@ impl ComputeGrid {
                 }
             }
         }
+        Ok(())
+    }
+
+    /// The grid index of the node a save file node was loaded into by `program_nodes`.
+    pub fn grid_index(&self, id: SaveFileNodeId) -> Option<usize> {
+        self.programmed.get(&id).copied()
+    }
+
+    /// Start counting, for each instruction of each compute node, how many times it runs and how
+    /// many cycles it stalls. This is off by default, since it slows the run down a little.
+    pub fn enable_profile(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(vec![vec![]; self.nodes.len()]);
+        }
+    }
+
+    /// The profile of each instruction of a compute node, if profiling is on.
+    pub fn profile(&self, idx: usize) -> Option<&[InstructionProfile]> {
+        let profile = self.profile.as_ref()?.get(idx)?;
+        match &self.nodes[idx].inner {
+            NodeType::Compute(c) if !c.instructions.is_empty() => Some(profile),
+            _ => None,
+        }
+    }
+
+    /// Start keeping a list of the values passed between nodes, to get with `take_transfers`.
+    pub fn enable_transfers(&mut self) {
+        if self.transfers.is_none() {
+            self.transfers = Some(vec![]);
+        }
+    }
+
+    /// The values passed between nodes since this was last called, in the order they were taken,
+    /// if `enable_transfers` was called.
+    pub fn take_transfers(&mut self) -> Vec<Transfer> {
+        self.transfers.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Save everything about the grid as it is now, to go back to with `restore`.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(self.clone())
+    }
+
+    /// Check that the parts of a grid read from a file fit together, so that running it can't go
+    /// out of bounds. Returns what's wrong if they don't.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let len = self.nodes.len();
+        if self.row_width != PUZZLE_WIDTH || len != PUZZLE_WIDTH * PUZZLE_HEIGHT {
+            return Err(format!("{} nodes in rows of {}", len, self.row_width));
+        }
+        let lists = [
+            ("asleep", self.asleep.len()),
+            ("read_blocked", self.read_blocked.len()),
+            ("stats", self.stats.len()),
+            ("profile", self.profile.as_ref().map_or(len, Vec::len)),
+        ];
+        for (name, list_len) in lists {
+            if list_len != len {
+                return Err(format!("{} has {} entries for {} nodes", name, list_len, len));
+            }
+        }
+        if let Some((&(idx, port), _)) = self.external.iter().find(|((idx, _), _)| *idx >= len) {
+            return Err(format!("I/O node on {} of node {}, which doesn't exist", port, idx));
+        }
+        if let Some((id, idx)) = self.programmed.iter().find(|(_, idx)| **idx >= len) {
+            return Err(format!("save file node {} is programmed into node {}, which doesn't exist",
+                id.0, idx));
+        }
+
+        let nodes = self.nodes.iter()
+            .enumerate()
+            .map(|(idx, node)| (NodeLocation::Grid(idx), node))
+            .chain(self.external_nodes());
+        for (location, node) in nodes {
+            let result = match &node.inner {
+                NodeType::Compute(c) => c.check(),
+                NodeType::Output(o) => o.check(),
+                NodeType::Visualization(v) => v.check(),
+                NodeType::Broken(_) | NodeType::Stack(_) | NodeType::Input(_) => Ok(()),
+            };
+            result.map_err(|e| format!("{}: {}", location, e))?;
+        }
+        Ok(())
+    }
+
+    /// Put the grid back the way it was when the snapshot was taken.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        *self = snapshot.grid().clone();
+    }
+
+    /// Get a node in the grid by its row-major index.
+    pub fn node(&self, idx: usize) -> Option<&Node> {
+        self.nodes.get(idx)
+    }
+
+    /// The I/O nodes attached to the grid, in order of where they are attached.
+    pub fn external_nodes(&self) -> impl Iterator<Item=(NodeLocation, &Node)> + '_ {
+        self.external.iter()
+            .map(|((idx, port), node)| (NodeLocation::External(*idx, *port), node))
     }
 
     fn compute_nodes(&self) -> impl Iterator<Item=&ComputeNode> + '_ {
@@ -102,18 +444,153 @@ impl ComputeGrid {
             .fold(0, |acc, node| acc + node.instructions.len())
     }
 
-    pub fn step(&mut self) -> Option<bool> {
-        self.read();
-        self.compute();
-        self.write();
-        self.advance();
+    /// Number of cycles completed so far.
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    /// The phase of the cycle which will run next.
+    pub fn phase(&self) -> CycleStep {
+        self.phase
+    }
+
+    /// Run the rest of the current cycle. Returns the outcome if the run ended in this cycle.
+    pub fn step(&mut self) -> Result<Option<Outcome>, Error> {
+        loop {
+            let outcome = self.step_phase()?;
+            if self.phase == CycleStep::Read {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Run just the next phase of the current cycle. Returns the outcome if this phase finished
+    /// the cycle and the run ended.
+    pub fn step_phase(&mut self) -> Result<Option<Outcome>, Error> {
+        self.run_phase::<true>()
+    }
+
+    /// Run until the run ends, or until `max_cycles` more cycles have finished, as fast as
+    /// possible. From partway through a cycle, finishing it counts as one, and `Some(0)` runs
+    /// nothing at all. Neither the grid nor its nodes log anything, and the checks for whether
+    /// logging is turned on, which otherwise take up a good part of the time, are left out too.
+    /// Returns the outcome, if the run ended, and the number of cycles run in total.
+    pub fn run_to_completion(&mut self, max_cycles: Option<usize>)
+        -> Result<(Option<Outcome>, usize), Error>
+    {
+        if max_cycles == Some(0) {
+            return Ok((None, self.cycle));
+        }
+        let stop_cycle = max_cycles.map(|n| self.cycle + n);
+        loop {
+            if let Some(outcome) = self.run_phase::<false>()? {
+                return Ok((Some(outcome), self.cycle));
+            }
+            if self.phase == CycleStep::Read && Some(self.cycle) == stop_cycle {
+                return Ok((None, self.cycle));
+            }
+        }
+    }
+
+    fn run_phase<const LOG: bool>(&mut self) -> Result<Option<Outcome>, Error> {
+        match self.phase {
+            CycleStep::Read => {
+                self.transferred = false;
+                self.breakpoint_hits.clear();
+                self.read::<LOG>()?;
+                self.phase = CycleStep::Compute;
+            }
+            CycleStep::Compute => {
+                self.compute::<LOG>()?;
+                self.phase = CycleStep::Write;
+            }
+            CycleStep::Write => {
+                self.write::<LOG>()?;
+                self.phase = CycleStep::Advance;
+            }
+            CycleStep::Advance => {
+                self.advance::<LOG>()?;
+                self.phase = CycleStep::Read;
+                self.cycle += 1;
+                return Ok(self.end_cycle());
+            }
+        }
+        Ok(None)
+    }
+
+    /// Debugger: run the next phase.
+    pub fn debug_step_phase(&mut self) -> Result<StopReason, Error> {
+        let outcome = self.step_phase()?;
+        Ok(self.stop_reason(outcome).unwrap_or(StopReason::Stepped))
+    }
+
+    /// Debugger: run the rest of the current cycle.
+    pub fn debug_step_cycle(&mut self) -> Result<StopReason, Error> {
+        let outcome = self.step()?;
+        Ok(self.stop_reason(outcome).unwrap_or(StopReason::Stepped))
+    }
+
+    /// Debugger: run until a node reaches a breakpoint, the run ends, or `max_cycles` more cycles
+    /// have finished.
+    ///
+    /// A node stops the run when it moves on to an instruction with a breakpoint, so a node
+    /// which is already sitting at a breakpoint won't stop it again right away. This makes it
+    /// suitable for continuing after a breakpoint.
+    pub fn run_until_breakpoint(&mut self, max_cycles: Option<usize>)
+        -> Result<StopReason, Error>
+    {
+        let stop_cycle = max_cycles.map(|n| self.cycle + n);
+        loop {
+            let outcome = self.step()?;
+            if let Some(reason) = self.stop_reason(outcome) {
+                return Ok(reason);
+            }
+            if let Some(stop_cycle) = stop_cycle {
+                if self.cycle >= stop_cycle {
+                    return Ok(StopReason::Stepped);
+                }
+            }
+        }
+    }
+
+    /// Debugger: continue running until the next breakpoint or the end of the run.
+    pub fn resume(&mut self) -> Result<StopReason, Error> {
+        self.run_until_breakpoint(None)
+    }
+
+    fn stop_reason(&self, outcome: Option<Outcome>) -> Option<StopReason> {
+        if let Some(outcome) = outcome {
+            Some(StopReason::Finished(outcome))
+        } else if self.phase == CycleStep::Read && !self.breakpoint_hits.is_empty() {
+            Some(StopReason::Breakpoint(self.breakpoint_hits.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn end_cycle(&mut self) -> Option<Outcome> {
+        self.count_stats();
 
         let mut all_verified = true;
-        for node in self.external.values() {
+        for ((idx, port), node) in &self.external {
             match node.verify_state() {
                 Some(VerifyState::Finished) => (),
                 Some(VerifyState::Failed) => {
-                    return Some(false);
+                    let mismatch = match &node.inner {
+                        NodeType::Output(output) => Some(Mismatch {
+                            node: *idx,
+                            port: *port,
+                            position: output.position(),
+                            expected: output.expected()[output.position()],
+                            received: *output.received().last()
+                                .expect("output failed without receiving anything"),
+                            cycle: self.cycle,
+                            expected_values: output.expected().to_vec(),
+                            received_values: output.received().to_vec(),
+                        }),
+                        _ => None,
+                    };
+                    return Some(Outcome::Incorrect(mismatch));
                 }
                 Some(VerifyState::Blocked) | Some(VerifyState::Okay) => { all_verified = false; }
                 None => ()
@@ -121,17 +598,115 @@ impl ComputeGrid {
         }
 
         if all_verified {
-            Some(true)
+            return Some(Outcome::Correct);
+        }
+
+        // Compare in place, to avoid building a new vector every cycle.
+        let nodes = self.nodes.iter().chain(self.external.values());
+        let mut changed = false;
+        if self.progress.len() == self.nodes.len() + self.external.len() {
+            for (old, node) in self.progress.iter_mut().zip(nodes) {
+                let new = node.progress_state();
+                if *old != new {
+                    *old = new;
+                    changed = true;
+                }
+            }
+        } else {
+            self.progress = nodes.map(Node::progress_state).collect();
+            changed = true;
+        }
+
+        if !self.transferred && !changed {
+            let blocked = self.blocked_nodes();
+            debug!("no progress made; deadlocked: {:?}", blocked);
+            Some(Outcome::Deadlock(blocked))
         } else {
             None
         }
     }
 
-    fn get_neighbor(&mut self, idx: usize, port: Port) -> Option<(&mut Node, Option<usize>)> {
+    /// Add what each compute node did in the cycle that just finished to its stats.
+    fn count_stats(&mut self) {
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let c = match &node.inner {
+                NodeType::Compute(c) => c,
+                _ => continue,
+            };
+            let stats = &mut self.stats[idx];
+            let profile = self.profile.as_mut().map(|p| instruction_profile(&mut p[idx], c.pc));
+            if c.instructions.is_empty() {
+                stats.idle += 1;
+            } else if self.read_blocked[idx] {
+                stats.blocked_read += 1;
+                if let Some(profile) = profile {
+                    profile.blocked_read += 1;
+                }
+            } else if node.step == CycleStep::Write && node.pending_output.is_some() {
+                stats.blocked_write += 1;
+                if let Some(profile) = profile {
+                    profile.blocked_write += 1;
+                }
+            } else {
+                stats.executing += 1;
+            }
+            self.read_blocked[idx] = false;
+        }
+    }
+
+    /// How each node in the grid has spent its cycles so far, by row-major index. Only compute
+    /// nodes are counted; the stats for other nodes stay at zero.
+    pub fn node_stats(&self) -> &[NodeStats] {
+        &self.stats
+    }
+
+    /// List the nodes which are currently waiting to read or write a value.
+    pub fn blocked_nodes(&self) -> Vec<BlockedNode> {
+        let mut blocked = vec![];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if let NodeType::Compute(c) = &node.inner {
+                let wait = if let Some((port, _val)) = node.pending_output {
+                    Some((CycleStep::Write, port))
+                } else if node.step == CycleStep::Read {
+                    c.read_port().map(|port| (CycleStep::Read, port))
+                } else {
+                    None
+                };
+                if let Some((step, port)) = wait {
+                    blocked.push(BlockedNode {
+                        location: NodeLocation::Grid(idx),
+                        type_name: node.type_name(),
+                        step,
+                        port,
+                    });
+                }
+            }
+        }
+
+        for ((idx, rel_port), node) in &self.external {
+            let step = match (&node.inner, node.verify_state()) {
+                (NodeType::Input(_), _) if node.pending_output.is_some() => CycleStep::Write,
+                (_, Some(VerifyState::Blocked)) => CycleStep::Read,
+                _ => continue,
+            };
+            blocked.push(BlockedNode {
+                location: NodeLocation::External(*idx, *rel_port),
+                type_name: node.type_name(),
+                step,
+                port: rel_port.opposite().unwrap_or(*rel_port),
+            });
+        }
+
+        blocked
+    }
+
+    fn get_neighbor(&mut self, idx: usize, port: Port)
+        -> Result<Option<(&mut Node, Option<usize>)>, ErrorKind>
+    {
         if let Some(node) = self.external.get_mut(&(idx, port)) {
-            Some((node, None))
+            Ok(Some((node, None)))
         } else {
-            match port {
+            Ok(match port {
                 Port::UP => {
                     if idx >= self.row_width {
                         let n = idx - self.row_width;
@@ -164,137 +739,258 @@ impl ComputeGrid {
                         None
                     }
                 }
-                _ => panic!("can't get neighbor {:?} ya dingus", port)
+                Port::ANY | Port::LAST => return Err(ErrorKind::InvalidPort(port)),
+            })
+        }
+    }
+
+    /// A node has a new value to write, so wake up any neighbors that might read it.
+    fn wake_neighbors(&mut self, idx: usize, port: Port) {
+        let ports = match port {
+            Port::ANY => &[Port::LEFT, Port::RIGHT, Port::UP, Port::DOWN][..],
+            _ => std::slice::from_ref(&port),
+        };
+        for port in ports {
+            if let Ok(Some((_node, Some(n)))) = self.get_neighbor(idx, *port) {
+                self.asleep[n] = false;
             }
         }
     }
 
-    pub fn read(&mut self) {
-        debug!("begin READ step");
+    fn read<const LOG: bool>(&mut self) -> Result<(), Error> {
+        log_if!(LOG, debug, "begin READ step");
 
         for idx in 0 .. self.nodes.len() {
             if let NodeType::Broken(_) = self.nodes[idx].inner {
                 continue;
             }
+            if self.skip_blocked && self.asleep[idx] {
+                self.read_blocked[idx] = true;
+                continue;
+            }
+            if self.skip_blocked && self.nodes[idx].idle(CycleStep::Read) {
+                continue;
+            }
+
+            let at_node = |e: Error| e.at_node(NodeLocation::Grid(idx));
 
             // get readable values from neighbors
-            let mut avail_reads = vec![];
+            let mut avail_reads = [(Port::ANY, None); 4];
+            let mut num_avail = 0;
 
-            let mut add_value_from = |attached_port: Port| {
-                if let Some((node, _idx)) = self.get_neighbor(idx, attached_port) {
+            let mut add_value_from = |attached_port: Port| -> Result<(), ErrorKind> {
+                if let Some((node, _idx)) = self.get_neighbor(idx, attached_port)? {
                     if let Some((port, val)) = node.pending_output() {
-                        if port == attached_port.opposite() || port == Port::ANY {
-                            avail_reads.push((attached_port, Some(val)));
+                        if Some(port) == attached_port.opposite() || port == Port::ANY {
+                            avail_reads[num_avail] = (attached_port, Some(val));
+                            num_avail += 1;
                         }
                     }
                 }
+                Ok(())
             };
 
             // The order is important because it affects which port completes first for an ANY read.
-            add_value_from(Port::LEFT);
-            add_value_from(Port::RIGHT);
-            add_value_from(Port::UP);
-            add_value_from(Port::DOWN);
+            for port in &[Port::LEFT, Port::RIGHT, Port::UP, Port::DOWN] {
+                add_value_from(*port).map_err(|e| at_node(e.into()))?;
+            }
+            let avail_reads = &mut avail_reads[.. num_avail];
 
             // Step the node!
 
-            debug!("node {}", idx);
-            let result = self.nodes[idx].read(avail_reads.as_mut_slice());
+            log_if!(LOG, debug, "node {}", idx);
+            let result = self.nodes[idx].read::<LOG>(avail_reads).map_err(at_node)?;
             if result != StepResult::Nothing {
-                debug!("  result: {:?}", result);
+                log_if!(LOG, debug, "  result: {:?}", result);
+            }
+            if let StepResult::IO(_) = result {
+                // Nothing it wants to read is there, and nothing will be until a neighbor writes.
+                self.asleep[idx] = true;
+                self.read_blocked[idx] = true;
             }
 
-            for (port, val) in &avail_reads {
+            for (port, val) in avail_reads.iter() {
                 if val.is_none() {
                     // the value was taken
-                    let (node, idx) = self.get_neighbor(idx, *port).unwrap();
-                    if let Some(idx) = idx {
-                        debug!("completing write for node {}", idx);
+                    let (node, neighbor) = self.get_neighbor(idx, *port)
+                        .map_err(|e| at_node(e.into()))?
+                        .expect("value was read from a nonexistent neighbor");
+                    if let Some(neighbor) = neighbor {
+                        log_if!(LOG, debug, "completing write for node {}", neighbor);
                     } else {
-                        debug!("completing write for {} node", node.type_name());
+                        log_if!(LOG, debug, "completing write for {} node", node.type_name());
                     }
+                    let value = node.pending_output.map(|(_port, value)| value);
                     node.complete_write(*port);
+                    self.transferred = true;
+
+                    if let (Some(transfers), Some(value)) = (&mut self.transfers, value) {
+                        transfers.push(Transfer {
+                            from: match neighbor {
+                                Some(neighbor) => NodeLocation::Grid(neighbor),
+                                None => NodeLocation::External(idx, *port),
+                            },
+                            to: NodeLocation::Grid(idx),
+                            port: port.opposite().unwrap_or(*port),
+                            value,
+                        });
+                    }
                 }
             }
         }
 
         // Now step the I/O nodes
         for ((idx, rel_port), ref mut node) in &mut self.external {
-            let mut avail_reads = vec![];
+            let at_node = |e: Error| e.at_node(NodeLocation::External(*idx, *rel_port));
+            let mut avail_read = None;
+            let mut offered = None;
 
             if let Some((dest_port, value)) = self.nodes[*idx].pending_output() {
+                offered = Some(value);
                 if dest_port == Port::ANY || dest_port == *rel_port {
-                    avail_reads.push((rel_port.opposite(), Some(value))); // port doesn't matter actually
+                    // port doesn't matter actually
+                    let port = rel_port.opposite()
+                        .ok_or_else(|| at_node(ErrorKind::InvalidPort(*rel_port).into()))?;
+                    avail_read = Some((port, Some(value)));
                 }
             }
+            let avail_reads = match &mut avail_read {
+                Some(read) => std::slice::from_mut(read),
+                None => &mut [],
+            };
 
-            debug!("{} port", node.type_name());
-            let result = node.read(avail_reads.as_mut_slice());
+            log_if!(LOG, debug, "{} port", node.type_name());
+            let result = node.read::<LOG>(avail_reads).map_err(at_node)?;
             if result != StepResult::Nothing {
-                debug!("  result: {:?}", result);
+                log_if!(LOG, debug, "  result: {:?}", result);
             }
 
-            if let Some((_port, val)) = avail_reads.first() {
-                if val.is_none() {
-                    // the value was taken
-                    debug!("completing write for node {}", idx);
-                    self.nodes[*idx].complete_write(*rel_port);
+            if let Some((_port, None)) = avail_read {
+                // the value was taken
+                log_if!(LOG, debug, "completing write for node {}", idx);
+                self.nodes[*idx].complete_write(*rel_port);
+                self.transferred = true;
+
+                if let (Some(transfers), Some(value)) = (&mut self.transfers, offered) {
+                    transfers.push(Transfer {
+                        from: NodeLocation::Grid(*idx),
+                        to: NodeLocation::External(*idx, *rel_port),
+                        port: *rel_port,
+                        value,
+                    });
                 }
             }
         }
+
+        Ok(())
     }
 
-    fn compute(&mut self) {
-        debug!("begin COMPUTE step");
+    fn compute<const LOG: bool>(&mut self) -> Result<(), Error> {
+        log_if!(LOG, debug, "begin COMPUTE step");
         for idx in 0 .. self.nodes.len() {
             if let NodeType::Broken(_) = self.nodes[idx].inner {
                 continue;
             }
+            if self.skip_blocked && self.nodes[idx].idle(CycleStep::Compute) {
+                continue;
+            }
 
-            let result = self.nodes[idx].compute();
+            let result = self.nodes[idx].compute::<LOG>()
+                .map_err(|e| e.at_node(NodeLocation::Grid(idx)))?;
             if result != StepResult::Nothing {
-                debug!("node {}: {:?}", idx, result);
+                log_if!(LOG, debug, "node {}: {:?}", idx, result);
             }
         }
-        for node in self.external.values_mut() {
-            node.compute();
+        for ((idx, rel_port), node) in &mut self.external {
+            node.compute::<LOG>().map_err(|e| e.at_node(NodeLocation::External(*idx, *rel_port)))?;
         }
+        Ok(())
     }
 
-    fn write(&mut self) {
-        debug!("begin WRITE step");
+    fn write<const LOG: bool>(&mut self) -> Result<(), Error> {
+        log_if!(LOG, debug, "begin WRITE step");
 
         for idx in 0 .. self.nodes.len() {
             if let NodeType::Broken(_) = self.nodes[idx].inner {
                 continue;
             }
+            if self.skip_blocked && self.nodes[idx].idle(CycleStep::Write) {
+                continue;
+            }
 
-            debug!("node {}", idx);
-            let result = self.nodes[idx].write();
+            log_if!(LOG, debug, "node {}", idx);
+            let before = self.nodes[idx].pending_output;
+            let result = self.nodes[idx].write::<LOG>()
+                .map_err(|e| e.at_node(NodeLocation::Grid(idx)))?;
             if result != StepResult::Nothing {
-                debug!("  result: {:?}", result);
+                log_if!(LOG, debug, "  result: {:?}", result);
+            }
+            match self.nodes[idx].pending_output {
+                Some((port, _val)) if self.nodes[idx].pending_output != before => {
+                    self.wake_neighbors(idx, port);
+                }
+                _ => (),
             }
         }
 
-        for node in self.external.values_mut() {
-            debug!("{} port", node.type_name());
-            let result = node.write();
+        for ((idx, rel_port), node) in &mut self.external {
+            if self.skip_blocked && node.idle(CycleStep::Write) {
+                continue;
+            }
+            log_if!(LOG, debug, "{} port", node.type_name());
+            let result = node.write::<LOG>()
+                .map_err(|e| e.at_node(NodeLocation::External(*idx, *rel_port)))?;
             if result != StepResult::Nothing {
-                debug!("  result: {:?}", result);
+                log_if!(LOG, debug, "  result: {:?}", result);
+            }
+            if node.pending_output.is_some() {
+                self.asleep[*idx] = false;
             }
         }
+        Ok(())
     }
 
-    fn advance(&mut self) {
-        debug!("begin ADVANCE step");
+    fn advance<const LOG: bool>(&mut self) -> Result<(), Error> {
+        log_if!(LOG, debug, "begin ADVANCE step");
         for idx in 0 .. self.nodes.len() {
-            let result = self.nodes[idx].advance();
+            if self.skip_blocked && self.nodes[idx].idle(CycleStep::Advance) {
+                continue;
+            }
+            let pc = match &self.nodes[idx].inner {
+                NodeType::Compute(c) => c.pc,
+                _ => 0,
+            };
+            let result = self.nodes[idx].advance::<LOG>()
+                .map_err(|e| e.at_node(NodeLocation::Grid(idx)))?;
             if result != StepResult::Nothing {
-                debug!("node {}: {:?}", idx, result);
+                log_if!(LOG, debug, "node {}: {:?}", idx, result);
+            }
+            if let (StepResult::Okay, Some(profile)) = (&result, &mut self.profile) {
+                instruction_profile(&mut profile[idx], pc).executions += 1;
             }
+            if let (StepResult::Okay, NodeType::Compute(c)) = (&result, &self.nodes[idx].inner) {
+                if c.breakpoints.contains(&c.pc) {
+                    log_if!(LOG, debug, "node {} reached breakpoint at {}", idx, c.pc);
+                    self.breakpoint_hits.push(BreakpointHit { node: idx, pc: c.pc });
+                }
+            }
+        }
+        for ((idx, rel_port), node) in &mut self.external {
+            node.advance::<LOG>().map_err(|e| e.at_node(NodeLocation::External(*idx, *rel_port)))?;
         }
-        for node in self.external.values_mut() {
-            node.advance();
+        Ok(())
+    }
+
+    /// Print the input and output streams attached to the grid.
+    pub fn print_io(&self) {
+        for ((idx, port), node) in &self.external {
+            let location = NodeLocation::External(*idx, *port);
+            match &node.inner {
+                NodeType::Input(n) => println!("IN  {:16} {}", location, n),
+                NodeType::Output(n) => println!("OUT {:16} {}", location, n),
+                NodeType::Visualization(n) => println!("VIZ {:16} {}", location, n),
+                _ => (),
+            }
         }
     }
 
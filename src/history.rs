@@ -0,0 +1,189 @@
+//! Going back to earlier cycles of a run. Snapshots of the grid are kept every so often as it runs
+//! forward, and an earlier cycle is reached by restoring the last snapshot before it and running
+//! forward again from there.
+
+use crate::error::Error;
+use crate::grid::{ComputeGrid, NodeLocation, StopReason, Transfer};
+use crate::instr::Port;
+use crate::node::{CycleStep, NodeType};
+use crate::snapshot::Snapshot;
+
+/// Checkpoints of a run, taken every `interval` cycles. Once there are more than the limit, every
+/// other one is dropped and the interval doubles, so a long run doesn't use up too much memory, at
+/// the cost of running more cycles again to go back.
+pub struct History {
+    interval: usize,
+    max_checkpoints: usize,
+    checkpoints: Vec<Snapshot>, // in order of cycle
+}
+
+/// Something found by searching back through a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Found {
+    /// A node sent a value out of a port.
+    Write { node: usize, port: Port, value: i32 },
+
+    /// A node's ACC changed.
+    Acc { node: usize, from: i32, to: i32 },
+}
+
+impl History {
+    /// Start keeping the history of a grid, which can't be gone back past where it is now.
+    pub fn new(grid: &ComputeGrid) -> History {
+        History::with_limits(grid, 100, 64)
+    }
+
+    pub fn with_limits(grid: &ComputeGrid, interval: usize, max_checkpoints: usize) -> History {
+        History {
+            interval: interval.max(1),
+            max_checkpoints: max_checkpoints.max(2),
+            checkpoints: vec![grid.snapshot()],
+        }
+    }
+
+    /// The earliest cycle that can be gone back to.
+    pub fn start(&self) -> usize {
+        self.checkpoints[0].cycle()
+    }
+
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Take a checkpoint if the grid is at the start of a cycle that is due for one.
+    pub fn record(&mut self, grid: &ComputeGrid) {
+        let cycle = grid.cycle();
+        if grid.phase() != CycleStep::Read || !cycle.is_multiple_of(self.interval)
+            || cycle <= self.checkpoints.last().expect("history has no checkpoints").cycle()
+        {
+            return;
+        }
+        self.checkpoints.push(grid.snapshot());
+        if self.checkpoints.len() > self.max_checkpoints {
+            self.interval *= 2;
+            let start = self.start();
+            let interval = self.interval;
+            self.checkpoints.retain(|c| c.cycle() == start || c.cycle().is_multiple_of(interval));
+        }
+    }
+
+    /// Debugger: run the grid forward like `ComputeGrid::run_until_breakpoint`, taking checkpoints
+    /// as it goes.
+    pub fn run_until_breakpoint(&mut self, grid: &mut ComputeGrid, max_cycles: Option<usize>)
+        -> Result<StopReason, Error>
+    {
+        let stop_cycle = max_cycles.map(|n| grid.cycle() + n);
+        loop {
+            let reason = grid.debug_step_cycle()?;
+            self.record(grid);
+            if reason != StopReason::Stepped
+                || stop_cycle.is_some_and(|stop_cycle| grid.cycle() >= stop_cycle)
+            {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Debugger: run the next phase of the grid, taking a checkpoint if it starts a new cycle.
+    pub fn step_phase(&mut self, grid: &mut ComputeGrid) -> Result<StopReason, Error> {
+        let reason = grid.debug_step_phase()?;
+        self.record(grid);
+        Ok(reason)
+    }
+
+    /// Put the grid in the state it was in at the start of the cycle after `cycle`.
+    pub fn rewind_to(&self, grid: &mut ComputeGrid, cycle: usize) -> Result<(), Error> {
+        let checkpoint = self.checkpoints.iter()
+            .rev()
+            .find(|c| c.cycle() <= cycle)
+            .unwrap_or(&self.checkpoints[0]);
+        grid.restore(checkpoint);
+        while grid.cycle() < cycle {
+            grid.step()?;
+        }
+        Ok(())
+    }
+
+    /// Go back `count` cycles. From partway through a cycle, going back one cycle goes to the
+    /// start of it.
+    pub fn step_back(&self, grid: &mut ComputeGrid, count: usize) -> Result<(), Error> {
+        let now = if grid.phase() == CycleStep::Read { grid.cycle() } else { grid.cycle() + 1 };
+        self.rewind_to(grid, now.saturating_sub(count).max(self.start()))
+    }
+
+    /// Go back to the start of the last cycle in which the node sent a value out of the port, or
+    /// out of any port for ANY. Returns what was sent, and leaves the grid alone if it never sent
+    /// anything that way.
+    pub fn last_write(&self, grid: &mut ComputeGrid, node: usize, port: Port)
+        -> Result<Option<Found>, Error>
+    {
+        let sent = |t: &&Transfer| {
+            t.from == NodeLocation::Grid(node) && (port == Port::ANY || t.port == port)
+        };
+        self.search_back(grid, |_grid, transfers| {
+            transfers.unwrap_or_default().iter()
+                .rev()
+                .find(sent)
+                .map(|t| Found::Write { node, port: t.port, value: t.value })
+        })
+    }
+
+    /// Go back to the start of the last cycle in which the node's ACC changed. Returns how it
+    /// changed, and leaves the grid alone if it never did.
+    pub fn last_acc_change(&self, grid: &mut ComputeGrid, node: usize)
+        -> Result<Option<Found>, Error>
+    {
+        let acc = |grid: &ComputeGrid| match grid.node(node).map(|n| &n.inner) {
+            Some(NodeType::Compute(c)) => Some(c.acc),
+            _ => None,
+        };
+        let mut before = None;
+        self.search_back(grid, move |grid, transfers| {
+            let after = acc(grid);
+            let changed = match (transfers, before, after) {
+                (Some(_), Some(from), Some(to)) if from != to => {
+                    Some(Found::Acc { node, from, to })
+                }
+                _ => None,
+            };
+            before = after;
+            changed
+        })
+    }
+
+    /// Find the last finished cycle that `check` finds something in, by running the grid again
+    /// from each checkpoint, starting with the latest. `check` is called with the grid at the start
+    /// of each stretch, with no transfers, and then after each cycle with the values passed in it.
+    /// The grid is left at the start of the cycle found.
+    fn search_back(&self, grid: &mut ComputeGrid,
+        mut check: impl FnMut(&ComputeGrid, Option<&[Transfer]>) -> Option<Found>)
+        -> Result<Option<Found>, Error>
+    {
+        let end = grid.cycle();
+        for (i, checkpoint) in self.checkpoints.iter().enumerate().rev() {
+            if checkpoint.cycle() >= end {
+                continue;
+            }
+            let stop = self.checkpoints.get(i + 1).map_or(end, |next| next.cycle().min(end));
+
+            let mut replay = checkpoint.to_grid();
+            replay.enable_transfers();
+            replay.take_transfers();
+            check(&replay, None);
+            let mut found = None;
+            while replay.cycle() < stop {
+                replay.step()?;
+                let transfers = replay.take_transfers();
+                if let Some(thing) = check(&replay, Some(&transfers)) {
+                    found = Some((replay.cycle(), thing));
+                }
+            }
+
+            if let Some((cycle, thing)) = found {
+                self.rewind_to(grid, cycle - 1)?;
+                return Ok(Some(thing));
+            }
+        }
+        Ok(None)
+    }
+}
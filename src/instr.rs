@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
+use serde::{Deserialize, Serialize};
 
 /// IMPORTANT: this corresponds to the node identifier in the save file, which skips over broken
 /// nodes. The node index in ComputeGrid may be different.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct SaveFileNodeId(pub u8);
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Port {
     UP, DOWN, LEFT, RIGHT, ANY, LAST,
 }
@@ -17,24 +19,25 @@ impl Display for Port {
 }
 
 impl Port {
-    pub fn opposite(self) -> Port {
+    /// The port on the opposite side of a node. ANY and LAST have no opposite.
+    pub fn opposite(self) -> Option<Port> {
         match self {
-            Port::UP => Port::DOWN,
-            Port::DOWN => Port::UP,
-            Port::LEFT => Port::RIGHT,
-            Port::RIGHT => Port::LEFT,
-            _ => panic!("can't get opposite of {:?}", self)
+            Port::UP => Some(Port::DOWN),
+            Port::DOWN => Some(Port::UP),
+            Port::LEFT => Some(Port::RIGHT),
+            Port::RIGHT => Some(Port::LEFT),
+            Port::ANY | Port::LAST => None,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Register {
     ACC, NIL,
     // excludes BAK because it cannot be addressed
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Src {
     Register(Register),
     Port(Port),
@@ -51,7 +54,7 @@ impl Display for Src {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Dst {
     Register(Register),
     Port(Port),
@@ -66,7 +69,7 @@ impl Display for Dst {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Instruction {
     NOP,
     MOV(Src, Dst),
@@ -104,9 +107,93 @@ impl Display for Instruction {
     }
 }
 
+impl Instruction {
+    /// The source operand of the instruction, if it has one.
+    pub fn src(&self) -> Option<&Src> {
+        match self {
+            Instruction::MOV(src, _) | Instruction::ADD(src) | Instruction::SUB(src)
+                | Instruction::JRO(src) => Some(src),
+            _ => None,
+        }
+    }
+
+    /// The label a jump instruction goes to, if it is one.
+    pub fn jump_target(&self) -> Option<&str> {
+        match self {
+            Instruction::JMP(l) | Instruction::JEZ(l) | Instruction::JNZ(l)
+                | Instruction::JGZ(l) | Instruction::JLZ(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    /// Turn the instruction into an Op by looking up its jump label, if it has one, in a map of
+    /// labels to instruction indices. Fails with the label if it isn't in the map.
+    pub fn link(&self, labels: &HashMap<String, usize>) -> Result<Op, &str> {
+        fn target<'a>(labels: &HashMap<String, usize>, label: &'a str) -> Result<usize, &'a str> {
+            labels.get(label).copied().ok_or(label)
+        }
+        Ok(match self {
+            Instruction::NOP => Op::NOP,
+            Instruction::MOV(src, dst) => Op::MOV(*src, *dst),
+            Instruction::SWP => Op::SWP,
+            Instruction::SAV => Op::SAV,
+            Instruction::ADD(src) => Op::ADD(*src),
+            Instruction::SUB(src) => Op::SUB(*src),
+            Instruction::NEG => Op::NEG,
+            Instruction::JMP(l) => Op::JMP(target(labels, l)?),
+            Instruction::JEZ(l) => Op::JEZ(target(labels, l)?),
+            Instruction::JNZ(l) => Op::JNZ(target(labels, l)?),
+            Instruction::JGZ(l) => Op::JGZ(target(labels, l)?),
+            Instruction::JLZ(l) => Op::JLZ(target(labels, l)?),
+            Instruction::JRO(src) => Op::JRO(*src),
+            Instruction::HCF => Op::HCF,
+        })
+    }
+}
+
+/// An instruction as it gets run, with the label of a jump resolved to the index of the
+/// instruction it goes to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Op {
+    NOP,
+    MOV(Src, Dst),
+    SWP,
+    SAV,
+    ADD(Src),
+    SUB(Src),
+    NEG,
+    JMP(usize),
+    JEZ(usize),
+    JNZ(usize),
+    JGZ(usize),
+    JLZ(usize),
+    JRO(Src),
+    HCF,
+}
+
+impl Op {
+    /// The source operand of the op, if it has one.
+    pub fn src(&self) -> Option<&Src> {
+        match self {
+            Op::MOV(src, _) | Op::ADD(src) | Op::SUB(src) | Op::JRO(src) => Some(src),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProgramItem {
     Instruction(Instruction),
     Label(String),
     Breakpoint,
 }
+
+impl Display for ProgramItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramItem::Instruction(i) => i.fmt(f),
+            ProgramItem::Label(l) => f.pad(&format!("{}:", l)),
+            ProgramItem::Breakpoint => f.pad("!"),
+        }
+    }
+}
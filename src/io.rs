@@ -1,8 +1,10 @@
 use crate::instr::Port;
+use crate::error::Error;
 use crate::node::{StepResult, ReadResult, WriteResult, AdvanceResult, NodeOps};
 use std::fmt::Write;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputNode {
     values: Vec<i32>,
     pos: usize,
@@ -20,22 +22,22 @@ impl InputNode {
 impl NodeOps for InputNode {
     // default impls for read and compute
 
-    fn write(&mut self) -> WriteResult {
+    fn write<const LOG: bool>(&mut self) -> Result<WriteResult, Error> {
         if let Some(value) = self.values.get(self.pos) {
-            trace!("writing {}", value);
-            StepResult::IO((Port::ANY, *value))
+            log_if!(LOG, trace, "writing {}", value);
+            Ok(StepResult::IO((Port::ANY, *value)))
         } else {
-            StepResult::Nothing
+            Ok(StepResult::Nothing)
         }
     }
 
-    fn advance(&mut self) -> AdvanceResult {
+    fn advance<const LOG: bool>(&mut self) -> Result<AdvanceResult, Error> {
         if self.pos < self.values.len() {
-            info!("{}", self);
+            log_if!(LOG, info, "{}", self);
             self.pos += 1;
-            StepResult::Okay
+            Ok(StepResult::Okay)
         } else {
-            StepResult::Nothing
+            Ok(StepResult::Nothing)
         }
     }
 }
@@ -55,10 +57,11 @@ impl std::fmt::Display for InputNode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutputNode {
     values: Vec<i32>,
     pos: usize,
+    received: Vec<i32>,
     verified: VerifyState,
 }
 
@@ -67,6 +70,7 @@ impl OutputNode {
         OutputNode {
             values,
             pos: 0,
+            received: vec![],
             verified: VerifyState::Blocked,
         }
     }
@@ -75,40 +79,69 @@ impl OutputNode {
         self.verified
     }
 
-    fn do_verify(&mut self, avail_read: &mut Option<&mut (Port, Option<i32>)>) -> VerifyState {
+    /// Every value the output expects.
+    pub fn expected(&self) -> &[i32] {
+        &self.values
+    }
+
+    /// The values received so far. After a wrong one, it's the last.
+    pub fn received(&self) -> &[i32] {
+        &self.received
+    }
+
+    /// Index of the next value to check, or of the wrong value after one was received.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Check that the position is inside the expected values.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.pos > self.values.len() {
+            return Err(format!("output position {} is past the end of {} values", self.pos,
+                self.values.len()));
+        }
+        Ok(())
+    }
+
+    fn do_verify<const LOG: bool>(&mut self, avail_read: &mut Option<&mut (Port, Option<i32>)>)
+        -> VerifyState
+    {
         if self.pos < self.values.len() {
             if let Some((port, val)) = avail_read {
                 let received = val.take().unwrap();
-                info!("checking value {} from {}", received, port);
-                info!("{}", self);
+                log_if!(LOG, info, "checking value {} from {}", received, port);
+                log_if!(LOG, info, "{}", self);
+                self.received.push(received);
                 if received == self.values[self.pos] {
-                    info!("value is correct");
+                    log_if!(LOG, info, "value is correct");
                     self.pos += 1;
                     if self.pos == self.values.len() {
-                        info!("finished now!");
+                        log_if!(LOG, info, "finished now!");
                         VerifyState::Finished
                     } else {
                         VerifyState::Okay
                     }
                 } else {
-                    error!("wrong input");
+                    log_if!(LOG, info, "wrong value: expected {}", self.values[self.pos]);
                     VerifyState::Failed
                 }
             } else {
-                trace!("waiting for input");
+                log_if!(LOG, trace, "waiting for input");
                 VerifyState::Blocked
             }
         } else {
-            trace!("finished");
+            log_if!(LOG, trace, "finished");
             VerifyState::Finished
         }
     }
 }
 impl NodeOps for OutputNode {
-    fn read(&mut self, avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
-        let state = self.do_verify(&mut avail_reads.get_mut(0));
+    fn read<const LOG: bool>(&mut self, avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
+        let state = self.do_verify::<LOG>(&mut avail_reads.get_mut(0));
         self.verified = state;
-        state.as_read_result()
+        Ok(state.as_read_result())
     }
 
     // default impls for compute, write, and advance.
@@ -129,7 +162,7 @@ impl std::fmt::Display for OutputNode {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum VerifyState {
     Failed,
     Okay,
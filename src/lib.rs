@@ -4,12 +4,33 @@
 #[macro_use] extern crate maplit;
 #[macro_use] extern crate num_derive;
 
+/// Log at the given level, but only if `$log` is true. Node and grid steps take it as a const
+/// parameter, so the fast path leaves out even the checks of the log level.
+macro_rules! log_if {
+    ($log:expr, $level:ident, $($arg:tt)+) => {
+        if $log {
+            $level!($($arg)+);
+        }
+    }
+}
+
 pub mod assembly;
 pub mod compute;
+pub mod debugger;
+pub mod error;
 pub mod grid;
+pub mod history;
 pub mod instr;
 pub mod io;
+pub mod link;
+pub mod minimize;
 pub mod node;
+pub mod profile;
 pub mod puzzles;
+pub mod report;
+pub mod snapshot;
 pub mod stack;
+pub mod syntax;
+pub mod trace;
+pub mod vcd;
 pub mod visualization;
@@ -0,0 +1,161 @@
+//! Checks on the labels in each node of a save file, done before the program is loaded, so the
+//! problems can be reported with where they are in the file.
+
+use crate::instr::SaveFileNodeId;
+use crate::syntax::{PieceKind, SourceFile};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+
+/// A problem with a label in a node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelIssue {
+    pub node: SaveFileNodeId,
+
+    /// Line number of the label or jump, starting at 1.
+    pub line: usize,
+
+    /// Column of the label or of the jump's label operand, starting at 1.
+    pub column: usize,
+
+    pub label: String,
+
+    pub kind: LabelIssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelIssueKind {
+    /// The label was already defined at this line and column in the same node.
+    Duplicate(usize, usize),
+
+    /// A jump goes to a label which isn't defined in the node.
+    Undefined,
+
+    /// No jump in the node goes to the label.
+    Unused,
+}
+
+impl LabelIssue {
+    /// Whether the issue stops the program from being loaded, rather than just being a warning.
+    pub fn is_error(&self) -> bool {
+        !matches!(self.kind, LabelIssueKind::Unused)
+    }
+}
+
+impl Display for LabelIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: node @{}: ", self.line, self.column, self.node.0)?;
+        match self.kind {
+            LabelIssueKind::Duplicate(line, column) => {
+                write!(f, "duplicate label {:?}, first defined at {}:{}", self.label, line, column)
+            }
+            LabelIssueKind::Undefined => write!(f, "undefined label {:?}", self.label),
+            LabelIssueKind::Unused => write!(f, "label {:?} is never jumped to", self.label),
+        }
+    }
+}
+
+#[derive(Default)]
+struct NodeLabels<'a> {
+    /// Where each label is defined, in order.
+    defs: Vec<(&'a str, usize, usize)>,
+
+    /// Where each jump's label is.
+    uses: Vec<(&'a str, usize, usize)>,
+}
+
+/// Find duplicate, undefined and unused labels in each node, in the order they appear in the file.
+/// Nodes with the same tag are treated as one node, the same way they are loaded.
+pub fn check_labels(file: &SourceFile) -> Vec<LabelIssue> {
+    let mut nodes = BTreeMap::<SaveFileNodeId, NodeLabels<'_>>::new();
+    for node in &file.nodes {
+        let labels = nodes.entry(node.id).or_default();
+        for line in &node.lines {
+            for piece in &line.pieces {
+                match &piece.kind {
+                    PieceKind::Label(label) => {
+                        labels.defs.push((label, line.line, piece.column));
+                    }
+                    PieceKind::Instruction(instr) => {
+                        if let Some(label) = instr.jump_target() {
+                            // the label is the last thing in the instruction's text
+                            let offset = piece.text.rfind(label).unwrap_or(0);
+                            labels.uses.push((label, line.line, piece.column + offset));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    let mut issues = vec![];
+    for (id, labels) in nodes {
+        let mut issue = |label: &str, line, column, kind| issues.push(LabelIssue {
+            node: id,
+            line,
+            column,
+            label: label.to_owned(),
+            kind,
+        });
+
+        let mut defined = HashMap::new();
+        for &(label, line, column) in &labels.defs {
+            match defined.get(label) {
+                Some(&(first_line, first_column)) => {
+                    issue(label, line, column, LabelIssueKind::Duplicate(first_line, first_column));
+                }
+                None => {
+                    defined.insert(label, (line, column));
+                    if !labels.uses.iter().any(|(used, _, _)| *used == label) {
+                        issue(label, line, column, LabelIssueKind::Unused);
+                    }
+                }
+            }
+        }
+        for &(label, line, column) in &labels.uses {
+            if !defined.contains_key(label) {
+                issue(label, line, column, LabelIssueKind::Undefined);
+            }
+        }
+    }
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_labels() {
+        let input = b"@0\n\
+            START: MOV UP, ACC\n\
+            LOOP: JEZ END\n\
+            SUB 1\n\
+            START: JMP  LOOP\n\
+            @1\n\
+            UNUSED: NOP\n\
+            JMP LOOP\n\
+            @0\n\
+            END:";
+        let file = SourceFile::parse(input).unwrap();
+        let issue = |node, line, column, label: &str, kind| LabelIssue {
+            node: SaveFileNodeId(node),
+            line,
+            column,
+            label: label.to_owned(),
+            kind,
+        };
+        assert_eq!(
+            vec![
+                issue(0, 2, 1, "START", LabelIssueKind::Unused),
+                issue(0, 5, 1, "START", LabelIssueKind::Duplicate(2, 1)),
+                issue(1, 7, 1, "UNUSED", LabelIssueKind::Unused),
+                issue(1, 8, 5, "LOOP", LabelIssueKind::Undefined),
+            ],
+            check_labels(&file));
+        assert_eq!(
+            "5:1: node @0: duplicate label \"START\", first defined at 2:1",
+            check_labels(&file)[1].to_string());
+    }
+}
@@ -3,31 +3,224 @@
 #[macro_use] extern crate log;
 
 use clap::Parser;
-use rand::SeedableRng;
-
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
+use tis100::grid::{ComputeGrid, Outcome};
+use tis100::assembly::Nodes;
+use tis100::instr::ProgramItem;
+use tis100::node::NodeType;
+use tis100::puzzles::Puzzle;
+use tis100::report::{Failure, RunReport, TestSetsReport};
+use tis100::syntax::SourceFile;
+use tis100::trace::TraceWriter;
+use tis100::vcd::VcdWriter;
+
+/// Files a run is being written to as it goes.
+#[derive(Default)]
+struct Recording {
+    trace: Option<TraceWriter<BufWriter<File>>>,
+    vcd: Option<VcdWriter<BufWriter<File>>>,
+}
+
+impl Recording {
+    /// Start writing the files the solution asks for, for a grid that hasn't run yet.
+    fn start(solution: &SolutionArgs, grid: &mut ComputeGrid) -> Recording {
+        let create = |path: &Path| File::create(path)
+            .map(BufWriter::new)
+            .unwrap_or_else(|e| {
+                error!("Failed to create {:?}: {}", path, e);
+                exit(2);
+            });
+        Recording {
+            trace: solution.trace.as_ref()
+                .map(|path| TraceWriter::new(create(path), grid)),
+            vcd: solution.vcd.as_ref()
+                .map(|path| VcdWriter::new(create(path), grid)
+                    .unwrap_or_else(|e| {
+                        error!("Failed to write {:?}: {}", path, e);
+                        exit(2);
+                    })),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.trace.is_none() && self.vcd.is_none()
+    }
+
+    /// Write the cycle the grid just finished.
+    fn write_cycle(&mut self, grid: &mut ComputeGrid, outcome: Option<&Outcome>) {
+        let transfers = grid.take_transfers();
+        let mut result = Ok(());
+        if let Some(trace) = &mut self.trace {
+            result = result.and(trace.write_cycle(grid, &transfers, outcome));
+        }
+        if let Some(vcd) = &mut self.vcd {
+            result = result.and(vcd.write_cycle(grid, &transfers));
+        }
+        if let Err(e) = result {
+            error!("Failed to record the run: {}", e);
+            exit(2);
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(version, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short='d', long="debug")]
+    #[arg(short='d', long="debug", global=true)]
     debug: bool,
 
-    #[arg(short='v', long="verbose", action=clap::ArgAction::Count)]
+    #[arg(short='v', long="verbose", action=clap::ArgAction::Count, global=true)]
     verbose: u8,
 
+    /// How to print the result of a run. JSON is meant for scripts: it's the only thing printed to
+    /// stdout, and has the puzzle, whether it passed, cycles, nodes, instructions, and why it
+    /// failed, if it did. Batch runs give a list of them.
+    #[arg(long="format", value_enum, default_value_t=Format::Text, global=true)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    solution: Option<SolutionArgs>,
+}
+
+#[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Step through a solution interactively.
+    Debug(SolutionArgs),
+
+    /// Print a save file laid out the same way the game writes it, keeping comments and blank
+    /// lines.
+    Fmt(FmtArgs),
+
+    /// Run every save slot of every known puzzle in a directory, like the game's save directory,
+    /// and print a table of the results. Slots with no code in them are skipped.
+    Batch(BatchArgs),
+
+    /// Run a solution against test sets made from many seeds, to find inputs it fails on. The
+    /// failing seed and its input streams are printed, and `--seed` runs that set again.
+    Sweep(SweepArgs),
+
+    /// Shrink the inputs of a test set a solution fails on, to the smallest inputs it still fails
+    /// on the same way.
+    Minimize(MinimizeArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct MinimizeArgs {
     #[arg(short='p', long="puzzle")]
     puzzle_num: Option<String>,
 
+    /// The seed of the failing test set, like `sweep` prints.
+    #[arg(long="seed", default_value_t=0)]
+    seed: u64,
+
     savefile_path: PathBuf,
 }
 
-fn main() {
-    println!("TESSELLATED INTELLIGENCE SYSTEMS TIS-100 BIOS V2.0-R");
-    println!("COPYRIGHT (C) 2018, WILLIAM R. FRASER");
+#[derive(clap::Args, Debug)]
+struct SweepArgs {
+    #[arg(short='p', long="puzzle")]
+    puzzle_num: Option<String>,
+
+    /// Count nodes with more code than fits in the game as failing.
+    #[arg(long="strict")]
+    strict: bool,
+
+    /// How many seeds to try.
+    #[arg(short='n', long="count", default_value_t=1000)]
+    count: u64,
+
+    /// The first seed to try.
+    #[arg(long="start", default_value_t=0)]
+    start: u64,
+
+    /// Keep going after a failing seed, and list all the seeds that failed.
+    #[arg(long="all")]
+    all: bool,
+
+    savefile_path: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct BatchArgs {
+    /// Count nodes with more code than fits in the game as failing.
+    #[arg(long="strict")]
+    strict: bool,
+
+    save_dir: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct FmtArgs {
+    /// Overwrite the save file instead of printing it.
+    #[arg(short='w', long="write")]
+    write: bool,
+
+    savefile_path: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct SolutionArgs {
+    #[arg(short='p', long="puzzle")]
+    puzzle_num: Option<String>,
+
+    /// Refuse to run nodes with more code than fits in the game (15 lines of 18 characters),
+    /// instead of just warning about them.
+    #[arg(long="strict")]
+    strict: bool,
+
+    /// Count how often each instruction runs and stalls, and print the save file annotated with
+    /// the counts after the run. Only shown with the text format.
+    #[arg(long="profile")]
+    profile: bool,
+
+    /// Run this many test sets, made from seeds 0, 1, 2 and so on. Seed 0 is always the same set,
+    /// so it's the one to compare cycle counts with.
+    #[arg(long="tests", default_value_t=1, value_parser=clap::value_parser!(u64).range(1..),
+        conflicts_with="seeds")]
+    tests: u64,
+
+    /// Make the test sets from these seeds instead, separated by commas.
+    #[arg(long="seed", value_delimiter=',')]
+    seeds: Vec<u64>,
+
+    /// Write a line of JSON for each cycle to this file, with every node's registers and every
+    /// value passed between nodes, inputs, and outputs. Only the first test set is traced.
+    #[arg(long="trace")]
+    trace: Option<PathBuf>,
+
+    /// Write the first test set's run to this file as a Value Change Dump, for a waveform viewer
+    /// like GTKWave, with each node's registers and the values sent over each link.
+    #[arg(long="vcd")]
+    vcd: Option<PathBuf>,
+
+    savefile_path: PathBuf,
+}
+
+impl SolutionArgs {
+    /// The seed of each test set to run.
+    fn seeds(&self) -> Vec<u64> {
+        if self.seeds.is_empty() {
+            (0 .. self.tests).collect()
+        } else {
+            self.seeds.clone()
+        }
+    }
+}
 
+fn main() {
     let mut args = Args::parse();
     if args.debug {
         if args.verbose != 0 {
@@ -35,85 +228,651 @@ fn main() {
         }
         args.verbose = 4;
     }
-    let puzzle_num = args.puzzle_num
-        .take()
-        .unwrap_or_else(||
-            args.savefile_path.file_name().unwrap()
-                .to_str().unwrap()
-                .split('.')
-                .next()
-                .unwrap()
-                .to_owned());
 
     stderrlog::new()
         .verbosity(usize::from(args.verbose))
         .init()
         .unwrap();
 
-    let input = fs::read(&args.savefile_path)
+    match args.command {
+        Some(Command::Debug(solution)) => {
+            let mut report = new_report(&solution, solution.seeds()[0]);
+            let (mut grid, _file) = load(&solution, &mut report, Print::Text)
+                .unwrap_or_else(|_| exit(1));
+            if let Err(e) = tis100::debugger::run(&mut grid) {
+                error!("I/O error: {}", e);
+                exit(2);
+            }
+        }
+        Some(Command::Fmt(fmt_args)) => {
+            format(fmt_args);
+        }
+        Some(Command::Batch(batch_args)) => {
+            batch(batch_args, args.format);
+        }
+        Some(Command::Sweep(sweep_args)) => {
+            sweep(sweep_args);
+        }
+        Some(Command::Minimize(minimize_args)) => {
+            minimize(minimize_args);
+        }
+        None => {
+            let solution = args.solution.expect("missing save file arguments");
+            run_solution(&solution, args.verbose, args.format);
+        }
+    }
+}
+
+fn read_save_file(path: &Path) -> Vec<u8> {
+    fs::read(path)
         .unwrap_or_else(|e| {
-            error!("Failed to read {:?}: {}", args.savefile_path, e);
+            error!("Failed to read {:?}: {}", path, e);
             exit(2);
-        });
+        })
+}
 
-    let r = rand_chacha::ChaChaRng::from_seed([0;32]);
-    let p = tis100::puzzles::get_puzzle(&puzzle_num, r)
-        .unwrap_or_else(|| {
-            eprintln!("Unknown puzzle number {:?}", puzzle_num);
+fn format(args: FmtArgs) {
+    let input = read_save_file(&args.savefile_path);
+    let file = tis100::syntax::SourceFile::parse(&input)
+        .unwrap_or_else(|errors| {
+            for e in &errors {
+                error!("{}", e);
+            }
+            error!("{} parse errors", errors.len());
             exit(1);
         });
 
-    println!(" - SEGMENT {}: \"{}\" -", puzzle_num, p.name);
+    let output = file.format();
+    if args.write {
+        if let Err(e) = fs::write(&args.savefile_path, output) {
+            error!("Failed to write {:?}: {}", args.savefile_path, e);
+            exit(2);
+        }
+    } else {
+        print!("{}", output);
+    }
+}
 
-    let mut grid = tis100::grid::ComputeGrid::from_puzzle(p);
+/// The puzzle number from the arguments, or else from the start of the save file's name, like the
+/// game names them: "10981.0.txt" is the first save slot of puzzle 10981.
+fn puzzle_num(solution: &SolutionArgs) -> String {
+    solution.puzzle_num
+        .clone()
+        .unwrap_or_else(||
+            solution.savefile_path.file_name().unwrap()
+                .to_str().unwrap()
+                .split('.')
+                .next()
+                .unwrap()
+                .to_owned())
+}
+
+/// A report for running the solution, before anything is known about it but which puzzle it's for.
+fn new_report(solution: &SolutionArgs, seed: u64) -> RunReport {
+    let mut report = RunReport::new(&puzzle_num(solution), seed);
+    report.slot = solution.savefile_path.file_name()
+        .and_then(|name| tis100::puzzles::save_file_slot(name.to_str()?))
+        .map(|(_, slot)| slot);
+    report
+}
+
+/// What `load` and `run` print as they go.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Print {
+    /// Everything, for a person to read.
+    Text,
+
+    /// Only problems with the save file, to stderr, leaving stdout for the JSON report.
+    Problems,
+
+    /// Nothing; the caller prints what it needs from the report.
+    Nothing,
+}
+
+/// Set up a grid for the puzzle and program it from the save file. Problems are printed to stderr
+/// as they're found, and if the solution can't be loaded, they're also returned.
+fn load(solution: &SolutionArgs, report: &mut RunReport, print: Print)
+    -> Result<(ComputeGrid, SourceFile), Vec<String>>
+{
+    let text = print == Print::Text;
+    let problems = print != Print::Nothing;
+    if text {
+        println!("TESSELLATED INTELLIGENCE SYSTEMS TIS-100 BIOS V2.0-R");
+        println!("COPYRIGHT (C) 2018, WILLIAM R. FRASER");
+    }
 
-    match tis100::assembly::parse_save_file(&input) {
-        Ok(nodes) => {
-            grid.program_nodes(nodes);
+    let input = fs::read(&solution.savefile_path)
+        .map_err(|e| {
+            let msg = format!("Failed to read {:?}: {}", solution.savefile_path, e);
+            if problems {
+                error!("{}", msg);
+            }
+            vec![msg]
+        })?;
+
+    let violations = tis100::assembly::check_size_limits(&input);
+    for violation in violations.iter().filter(|_| problems) {
+        if solution.strict {
+            eprintln!("error: {}", violation);
+        } else {
+            eprintln!("warning: {}", violation);
+        }
+    }
+    if solution.strict && !violations.is_empty() {
+        return Err(violations.iter().map(ToString::to_string).collect());
+    }
+
+    let p = tis100::puzzles::get_puzzle(&report.puzzle, tis100::puzzles::seeded_rng(report.seed))
+        .ok_or_else(|| {
+            let msg = format!("Unknown puzzle number {:?}", report.puzzle);
+            if problems {
+                eprintln!("{}", msg);
+            }
+            vec![msg]
+        })?;
+
+    if text {
+        println!(" - SEGMENT {}: \"{}\" -", report.puzzle, p.name);
+    }
+    report.name = Some(p.name.to_owned());
+
+    let mut grid = ComputeGrid::from_puzzle(p);
+
+    let file = SourceFile::parse(&input)
+        .map_err(|errors| {
+            if problems {
+                for e in &errors {
+                    error!("{}", e);
+                }
+                error!("{} parse errors", errors.len());
+            }
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>()
+        })?;
+
+    let issues = tis100::link::check_labels(&file);
+    for issue in issues.iter().filter(|_| problems) {
+        if issue.is_error() {
+            eprintln!("error: {}", issue);
+        } else {
+            eprintln!("warning: {}", issue);
         }
-        Err((remaining, nodes)) => {
-            let pos = input.len() - remaining.len();
-            let (line, col) = input.iter()
-                .take(pos)
-                .fold((1, 0), |(mut line, mut col), byte| {
-                    if *byte == b'\n' {
-                        line += 1;
-                        col = 0;
-                    } else {
-                        col += 1;
+    }
+    if issues.iter().any(|issue| issue.is_error()) {
+        return Err(issues.iter()
+            .filter(|issue| issue.is_error())
+            .map(ToString::to_string)
+            .collect());
+    }
+
+    if let Err(e) = grid.program_nodes(file.nodes()) {
+        if problems {
+            error!("failed to load program: {}", e);
+        }
+        return Err(vec![format!("failed to load program: {}", e)]);
+    }
+
+    report.loaded(&grid);
+    if text {
+        println!("{} nodes programmed", report.nodes);
+        println!("{} total instructions", report.instructions);
+    }
+
+    if solution.profile {
+        grid.enable_profile();
+    }
+
+    Ok((grid, file))
+}
+
+/// A grid for the report's test set, programmed with nodes `load` has already read and checked.
+fn program_puzzle(solution: &SolutionArgs, report: &mut RunReport, nodes: &Nodes) -> ComputeGrid {
+    let puzzle = tis100::puzzles::get_puzzle(&report.puzzle,
+            tis100::puzzles::seeded_rng(report.seed))
+        .expect("puzzle loaded before");
+    report.name = Some(puzzle.name.to_owned());
+    let mut grid = ComputeGrid::from_puzzle(puzzle);
+    grid.program_nodes(nodes.clone()).expect("solution loaded before");
+    report.loaded(&grid);
+    if solution.profile {
+        grid.enable_profile();
+    }
+    grid
+}
+
+/// Run the grid until the solution passes or fails, recording how it went in the report, and
+/// printing it too for `Print::Text`.
+fn run(grid: &mut ComputeGrid, verbose: u8, report: &mut RunReport, print: Print,
+    recording: &mut Recording)
+{
+    let text = print == Print::Text;
+    let result = if verbose == 0 && recording.is_empty() {
+        grid.run_to_completion(None)
+            .map(|(outcome, cycles)|
+                (outcome.expect("run with no cycle limit ended without an outcome"), cycles))
+            .map_err(|e| (grid.cycle() + 1, e))
+    } else {
+        step_until_done(grid, verbose, recording)
+    };
+
+    let (outcome, cycles) = match result {
+        Ok(result) => result,
+        Err((cycle, e)) => {
+            if text {
+                println!("error in cycle {}: {}", cycle, e);
+            }
+            report.cycles = Some(cycle);
+            report.failure = Some(Failure::Error { message: e.to_string() });
+            return;
+        }
+    };
+    report.finished(&outcome, cycles);
+    if !text {
+        return;
+    }
+
+    match outcome {
+        Outcome::Correct => println!("correct solution in {} cycles", cycles),
+        Outcome::Incorrect(mismatch) => {
+            println!("incorrect solution in {} cycles", cycles);
+            if let Some(mismatch) = mismatch {
+                println!("{}", mismatch);
+                print!("{}", mismatch.side_by_side());
+            }
+        }
+        Outcome::Deadlock(blocked) => {
+            println!("deadlock in cycle {}", cycles);
+            for node in blocked {
+                println!("  {}", node);
+            }
+        }
+    }
+
+    print_stats(grid);
+}
+
+/// Run the solution against each of its test sets, print how it did, and exit with an error if it
+/// failed any of them.
+fn run_solution(solution: &SolutionArgs, verbose: u8, format: Format) {
+    let text = format == Format::Text;
+    let seeds = solution.seeds();
+    let single = seeds.len() == 1;
+    let mut reports = vec![];
+    let mut loaded: Option<(SourceFile, Nodes)> = None;
+    let mut profiled = None;
+    for (i, &seed) in seeds.iter().enumerate() {
+        let mut report = new_report(solution, seed);
+        let mut grid = match &loaded {
+            // Only the puzzle's inputs change from one set to the next, so the file is read once.
+            Some((_file, nodes)) => program_puzzle(solution, &mut report, nodes),
+            None => {
+                let print = if text { Print::Text } else { Print::Problems };
+                match load(solution, &mut report, print) {
+                    Ok((grid, file)) => {
+                        let nodes = file.nodes();
+                        loaded = Some((file, nodes));
+                        grid
+                    }
+                    Err(errors) => {
+                        report.failure = Some(Failure::Load { errors });
+                        reports.push(report);
+                        break;
                     }
-                    (line, col)
-                });
-            error!("parse error at {}:{} (offset {})", line, col, pos);
-            error!("parsed input up to that point: {:?}", nodes);
+                }
+            }
+        };
+
+        let print = if single && text { Print::Text } else { Print::Nothing };
+        let mut recording = if i == 0 {
+            Recording::start(solution, &mut grid)
+        } else {
+            Recording::default()
+        };
+        run(&mut grid, verbose, &mut report, print, &mut recording);
+        if i == 0 {
+            profiled = Some(grid);
+        }
+        reports.push(report);
+    }
+
+    let passed = reports.iter().all(|report| report.passed);
+    if single {
+        if !text {
+            println!("{}", reports[0].to_json());
+        }
+    } else {
+        let sets = TestSetsReport::new(reports);
+        if text {
+            for (i, report) in sets.sets.iter().enumerate() {
+                println!("test set {} (seed {}): {}", i + 1, report.seed, result_text(report));
+            }
+            match (sets.worst_cycles, sets.average_cycles) {
+                (Some(worst), Some(average)) => {
+                    println!("worst {} cycles, average {:.1} cycles over {} test sets",
+                        worst, average, sets.sets.len());
+                }
+                _ => {
+                    let failed = sets.sets.iter().filter(|report| !report.passed).count();
+                    println!("failed {} of {} test sets", failed, sets.sets.len());
+                }
+            }
+        } else {
+            println!("{}", sets.to_json());
+        }
+    }
+
+    if let (Some(grid), Some((file, _nodes))) = (profiled, &loaded) {
+        if text && solution.profile {
+            print_profile(file, &grid);
+        }
+    }
+
+    if !passed {
+        exit(1);
+    }
+}
+
+/// A line saying how a run went.
+fn result_text(report: &RunReport) -> String {
+    let cycles = report.cycles.unwrap_or_default();
+    match &report.failure {
+        None => format!("correct solution in {} cycles", cycles),
+        Some(Failure::Incorrect { mismatch: None }) => {
+            format!("incorrect solution in {} cycles", cycles)
+        }
+        Some(Failure::Incorrect { mismatch: Some(mismatch) }) => {
+            format!("incorrect solution in {} cycles: {}", cycles, mismatch)
+        }
+        Some(Failure::Deadlock { .. }) => format!("deadlock in cycle {}", cycles),
+        Some(Failure::Error { message }) => format!("error in cycle {}: {}", cycles, message),
+        Some(Failure::Load { .. }) => "failed to load".to_owned(),
+    }
+}
+
+/// Run every save slot in a directory and print a table of how each one did.
+fn batch(args: BatchArgs, format: Format) {
+    let entries = fs::read_dir(&args.save_dir)
+        .unwrap_or_else(|e| {
+            error!("Failed to read {:?}: {}", args.save_dir, e);
+            exit(2);
+        });
+
+    let mut slots = vec![];
+    for entry in entries {
+        let entry = entry.unwrap_or_else(|e| {
+            error!("Failed to read {:?}: {}", args.save_dir, e);
+            exit(2);
+        });
+        let name = entry.file_name();
+        let slot = name.to_str().and_then(tis100::puzzles::save_file_slot);
+        if let Some((number, slot)) = slot {
+            let order = tis100::puzzles::PUZZLE_NUMBERS.iter().position(|n| *n == number);
+            slots.push((order, slot, number, entry.path()));
+        }
+    }
+    slots.sort();
+
+    let mut reports = vec![];
+    let mut skipped = 0;
+    for (_, _, number, path) in slots {
+        if is_empty_save(&path) {
+            skipped += 1;
+            continue;
+        }
+        let solution = SolutionArgs {
+            puzzle_num: Some(number.to_owned()),
+            strict: args.strict,
+            profile: false,
+            tests: 1,
+            seeds: vec![],
+            trace: None,
+            vcd: None,
+            savefile_path: path,
+        };
+        let mut report = new_report(&solution, 0);
+        match load(&solution, &mut report, Print::Nothing) {
+            Ok((mut grid, _file)) => run(&mut grid, 0, &mut report, Print::Nothing,
+                &mut Recording::default()),
+            Err(errors) => report.failure = Some(Failure::Load { errors }),
+        }
+        reports.push(report);
+    }
+
+    let failed = reports.iter().filter(|report| !report.passed).count();
+    match format {
+        Format::Json => {
+            println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+        }
+        Format::Text => {
+            println!("{:8} {:>4}  {:10} {:>7} {:>5} {:>12}",
+                "PUZZLE", "SLOT", "RESULT", "CYCLES", "NODES", "INSTRUCTIONS");
+            for report in &reports {
+                let result = match &report.failure {
+                    None => "passed",
+                    Some(Failure::Load { .. }) => "load error",
+                    Some(Failure::Incorrect { .. }) => "incorrect",
+                    Some(Failure::Deadlock { .. }) => "deadlock",
+                    Some(Failure::Error { .. }) => "error",
+                };
+                let cycles = report.cycles.map_or_else(|| "-".to_owned(), |c| c.to_string());
+                println!("{:8} {:>4}  {:10} {:>7} {:>5} {:>12}",
+                    report.puzzle, report.slot.unwrap_or_default(), result, cycles,
+                    report.nodes, report.instructions);
+            }
+            println!("{} passed, {} failed, {} empty slots skipped",
+                reports.len() - failed, failed, skipped);
+            for report in &reports {
+                let errors = match &report.failure {
+                    Some(Failure::Load { errors }) => errors.clone(),
+                    Some(Failure::Error { message }) => vec![message.clone()],
+                    _ => vec![],
+                };
+                for error in errors {
+                    println!("{}.{}: {}", report.puzzle, report.slot.unwrap_or_default(), error);
+                }
+            }
+        }
+    }
+
+    if failed != 0 {
+        exit(1);
+    }
+}
+
+/// Run a solution with one seed after another, printing the test sets it fails on.
+fn sweep(args: SweepArgs) {
+    let end = args.start.checked_add(args.count).unwrap_or_else(|| {
+        eprintln!("--start {} and --count {} go past the last seed", args.start, args.count);
+        exit(2);
+    });
+
+    let solution = SolutionArgs {
+        puzzle_num: args.puzzle_num,
+        strict: args.strict,
+        profile: false,
+        tests: 1,
+        seeds: vec![],
+        trace: None,
+        vcd: None,
+        savefile_path: args.savefile_path,
+    };
+
+    // The save file is the same for every seed, so it's read once, and its problems shown once.
+    let mut report = new_report(&solution, args.start);
+    let nodes = match load(&solution, &mut report, Print::Problems) {
+        Ok((_grid, file)) => file.nodes(),
+        Err(_) => exit(1),
+    };
+
+    let mut failed = vec![];
+    for seed in args.start .. end {
+        let puzzle = tis100::puzzles::get_puzzle(&report.puzzle,
+                tis100::puzzles::seeded_rng(seed))
+            .expect("puzzle loaded before");
+        let result = run_puzzle(&puzzle, &nodes, None);
+        if result.passed {
+            continue;
+        }
+
+        println!("seed {}: {}", seed, result_text(&result));
+        for ((idx, port), values) in &puzzle.inputs {
+            println!("  input {} of node {}: {}", port, idx, list(values));
+        }
+        for ((idx, port), values) in &puzzle.outputs {
+            println!("  expected output {} of node {}: {}", port, idx, list(values));
+        }
+        println!("  run it again with --seed {0}, or shrink the inputs with minimize --seed {0}",
+            seed);
+
+        failed.push(seed);
+        if !args.all {
+            break;
+        }
+    }
+
+    if failed.is_empty() {
+        println!("passed all {} seeds from {}", args.count, args.start);
+    } else {
+        if args.all {
+            println!("{} of {} seeds failed: {}", failed.len(), args.count, list(&failed));
+        }
+        exit(1);
+    }
+}
+
+/// Shrink the inputs of a failing test set and print them.
+fn minimize(args: MinimizeArgs) {
+    let solution = SolutionArgs {
+        puzzle_num: args.puzzle_num,
+        strict: false,
+        profile: false,
+        tests: 1,
+        seeds: vec![args.seed],
+        trace: None,
+        vcd: None,
+        savefile_path: args.savefile_path,
+    };
+    let mut report = new_report(&solution, args.seed);
+    let nodes = match load(&solution, &mut report, Print::Problems) {
+        Ok((_grid, file)) => file.nodes(),
+        Err(_) => exit(1),
+    };
+    let puzzle = tis100::puzzles::get_puzzle(&report.puzzle,
+            tis100::puzzles::seeded_rng(args.seed))
+        .expect("puzzle loaded before");
+
+    let original = run_puzzle(&puzzle, &nodes, None);
+    if original.passed {
+        println!("seed {}: {}; nothing to shrink", args.seed, result_text(&original));
+        return;
+    }
+    let values = |p: &Puzzle| p.inputs.values().map(Vec::len).sum::<usize>();
+    println!("seed {}: {} with {} input values", args.seed, result_text(&original),
+        values(&puzzle));
+
+    // Smaller inputs shouldn't take longer, but they could make the solution loop forever.
+    let max_cycles = original.cycles.unwrap_or_default() * 2 + 100;
+    let failure = std::mem::discriminant(&original.failure);
+    let small = tis100::minimize::minimize(&puzzle, |p| {
+            let report = run_puzzle(p, &nodes, Some(max_cycles));
+            report.cycles.is_some() && std::mem::discriminant(&report.failure) == failure
+        })
+        .unwrap_or_else(|| {
+            eprintln!("puzzle {} has image outputs, which can't be worked out for new inputs",
+                report.puzzle);
             exit(1);
+        });
+
+    println!("shrunk to {} input values:", values(&small));
+    for ((idx, port), values) in &small.inputs {
+        println!("  input {} of node {}: {}", port, idx, list(values));
+    }
+    for ((idx, port), values) in &small.outputs {
+        println!("  expected output {} of node {}: {}", port, idx, list(values));
+    }
+    println!("{}", result_text(&run_puzzle(&small, &nodes, Some(max_cycles))));
+    exit(1);
+}
+
+/// Run a solution against a puzzle without printing anything. A run cut off by `max_cycles`
+/// hasn't passed, but has no cycles or failure.
+fn run_puzzle(puzzle: &Puzzle, nodes: &Nodes, max_cycles: Option<usize>) -> RunReport {
+    let mut report = RunReport::new("", 0);
+    let mut grid = ComputeGrid::from_puzzle(puzzle.clone());
+    grid.program_nodes(nodes.clone()).expect("solution loaded before");
+    match grid.run_to_completion(max_cycles) {
+        Ok((Some(outcome), cycles)) => report.finished(&outcome, cycles),
+        Ok((None, _)) => (),
+        Err(e) => {
+            report.cycles = Some(grid.cycle() + 1);
+            report.failure = Some(Failure::Error { message: e.to_string() });
         }
     }
+    report
+}
 
-    let num_nodes = grid.count_programmed_nodes();
-    let num_instructions = grid.count_instructions();
+/// Values separated by commas.
+fn list<T: ToString>(values: &[T]) -> String {
+    values.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Whether a save file has no code in any node, like the game writes for a slot that hasn't been
+/// used. Files that can't be read or parsed aren't empty, so they're run and their errors shown.
+fn is_empty_save(path: &Path) -> bool {
+    fs::read(path).ok()
+        .and_then(|input| SourceFile::parse(&input).ok())
+        .is_some_and(|file| file.nodes().values()
+            .all(|items| !items.iter().any(|item| matches!(item, ProgramItem::Instruction(_)))))
+}
+
+/// Print how each programmed node spent its cycles.
+fn print_stats(grid: &ComputeGrid) {
+    for (idx, stats) in grid.node_stats().iter().enumerate() {
+        if stats.cycles() != 0 && stats.idle != stats.cycles() {
+            println!("node {:2}: {}", idx, stats);
+        }
+    }
+}
 
-    println!("{} nodes programmed", num_nodes);
-    println!("{} total instructions", num_instructions);
+/// Print the save file annotated with the profile of each instruction, and the instructions which
+/// took the most cycles.
+fn print_profile(file: &SourceFile, grid: &ComputeGrid) {
+    println!();
+    print!("{}", tis100::profile::annotate(file, grid));
+    println!();
+    println!("hot spots:");
+    for (idx, pc, profile) in tis100::profile::hot_spots(grid, 5) {
+        let instr = match grid.node(idx).map(|node| &node.inner) {
+            Some(NodeType::Compute(c)) => c.instructions[pc].to_string(),
+            _ => String::new(),
+        };
+        println!("  node {:2} #{:<2} {:18} {:6} cycles ({} exec, {} read, {} write)",
+            idx, pc, instr, profile.cycles(), profile.executions, profile.blocked_read,
+            profile.blocked_write);
+    }
+}
 
+/// Run one cycle at a time, showing progress as it goes. An error comes with the cycle it
+/// happened in.
+fn step_until_done(grid: &mut ComputeGrid, verbose: u8, recording: &mut Recording)
+    -> Result<(Outcome, usize), (usize, tis100::error::Error)>
+{
     let mut cycle = 1;
     loop {
-        match args.verbose {
-            0 => (),
+        match verbose {
             1 => eprint!("\rcycle {}", cycle),
             _ => info!("--- start of cycle {} ---", cycle),
         }
-        if let Some(correct) = grid.step() {
-            if args.verbose == 1 {
-                eprint!("\r");
-            }
-            if correct {
-                print!("correct");
-            } else {
-                print!("incorrect");
-            }
-            println!(" solution in {} cycles", cycle);
-            break;
+        let result = grid.step();
+        if verbose == 1 && !matches!(result, Ok(None)) {
+            eprint!("\r");
+        }
+        if let Ok(outcome) = &result {
+            recording.write_cycle(grid, outcome.as_ref());
+        }
+        match result {
+            Ok(Some(outcome)) => return Ok((outcome, cycle)),
+            Ok(None) => (),
+            Err(e) => return Err((cycle, e)),
         }
         //grid.print();
         cycle += 1;
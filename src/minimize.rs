@@ -0,0 +1,107 @@
+//! Shrinking the inputs of a puzzle a solution fails on, to find a smaller set of inputs it still
+//! fails on, which is easier to work through by hand.
+
+use crate::puzzles::{Puzzle, Streams};
+
+/// Shrink the puzzle's inputs as far as possible while `fails` still says the solution fails on
+/// them. Values are removed from every input at once, since puzzles with several inputs read them
+/// in step, and then the values left are swapped for smaller ones from the same input. The
+/// outputs are always the ones the puzzle expects for the inputs.
+///
+/// Returns None if the puzzle can't work out outputs for new inputs.
+pub fn minimize(puzzle: &Puzzle, mut fails: impl FnMut(&Puzzle) -> bool) -> Option<Puzzle> {
+    // Only values that could have come up in the original inputs are used, so that the inputs
+    // stay the kind the puzzle gives.
+    let candidates = puzzle.inputs.iter()
+        .map(|(key, values)| {
+            let mut values = values.clone();
+            values.sort_by_key(|v| (v.abs(), *v));
+            values.dedup();
+            (*key, values)
+        })
+        .collect::<Streams>();
+
+    let mut best = puzzle.with_inputs(puzzle.inputs.clone())?;
+    loop {
+        let len = best.inputs.values().map(Vec::len).max().unwrap_or(0);
+        let mut changed = false;
+
+        // Remove runs of values, starting with big ones.
+        let mut size = len / 2;
+        while size > 0 {
+            let mut start = 0;
+            while start < best.inputs.values().map(Vec::len).max().unwrap_or(0) {
+                let inputs = best.inputs.iter()
+                    .map(|(key, values)| {
+                        let mut values = values.clone();
+                        let end = (start + size).min(values.len());
+                        values.drain(start.min(end) .. end);
+                        (*key, values)
+                    })
+                    .collect();
+                let smaller = best.with_inputs(inputs)?;
+                if fails(&smaller) {
+                    best = smaller;
+                    changed = true;
+                } else {
+                    start += size;
+                }
+            }
+            size /= 2;
+        }
+
+        // Make the values left simpler.
+        let keys = best.inputs.keys().copied().collect::<Vec<_>>();
+        for key in keys {
+            for i in 0 .. best.inputs[&key].len() {
+                let value = best.inputs[&key][i];
+                for &simpler in &candidates[&key] {
+                    if (simpler.abs(), simpler) >= (value.abs(), value) {
+                        break;
+                    }
+                    let mut inputs = best.inputs.clone();
+                    inputs.get_mut(&key).unwrap()[i] = simpler;
+                    let simpler = best.with_inputs(inputs)?;
+                    if fails(&simpler) {
+                        best = simpler;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if !changed {
+            return Some(best);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr::Port;
+
+    #[test]
+    fn test_minimize() {
+        let puzzle = crate::puzzles::get_puzzle("10981", crate::puzzles::seeded_rng(0)).unwrap();
+        let input = |p: &Puzzle| p.inputs[&(1, Port::UP)].clone();
+
+        // "fails" whenever there's a value over 90 after a value under 20
+        let original = input(&puzzle);
+        let fails = |p: &Puzzle| {
+            let input = input(p);
+            (1 .. input.len()).any(|i| input[i] > 90 && input[.. i].iter().any(|v| *v < 20))
+        };
+        assert!(fails(&puzzle));
+
+        let small = minimize(&puzzle, fails).unwrap();
+        let small_input = input(&small);
+        assert_eq!(2, small_input.len());
+        assert!(small_input[0] < 20 && small_input[1] > 90);
+        // the smallest values from the original input that still fail
+        let smallest_over_90 = original.iter().filter(|v| **v > 90).min().unwrap();
+        assert_eq!(vec![*original.iter().min().unwrap(), *smallest_over_90], small_input);
+        assert_eq!(vec![small_input[0] * 2, small_input[1] * 2], small.outputs[&(10, Port::DOWN)]);
+    }
+}
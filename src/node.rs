@@ -1,17 +1,19 @@
 use crate::compute::ComputeNode;
+use crate::error::{Error, ErrorKind};
 use crate::stack::StackNode;
 use crate::instr::{Port, ProgramItem};
 use crate::io::{InputNode, OutputNode, VerifyState};
 use crate::visualization::VisualizationNode;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub inner: NodeType,
     pub step: CycleStep,
     pub pending_output: Option<(Port, i32)>, // port is relative to this node
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NodeType {
     Broken(BrokenNode),
     Compute(ComputeNode),
@@ -21,7 +23,7 @@ pub enum NodeType {
     Visualization(VisualizationNode),
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 pub enum CycleStep {
     Read, Compute, Write, Advance,
 }
@@ -37,6 +39,15 @@ impl std::fmt::Display for CycleStep {
     }
 }
 
+/// The parts of a node's state that change whenever it does anything. If none of these change over
+/// a whole cycle, and no values were transferred, the node made no progress.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressState {
+    step: CycleStep,
+    pending_output: Option<(Port, i32)>,
+    registers: Option<(usize, i32, i32, Port, Option<i32>)>,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum StepResult<Output> {
     /// Nothing happened.
@@ -57,22 +68,26 @@ pub type ComputeResult = StepResult<()>;
 pub type WriteResult = StepResult<(Port, i32)>;
 pub type AdvanceResult = StepResult<()>;
 
+/// The steps of a cycle. `LOG` is false when running as fast as possible, and then nothing is
+/// logged.
 pub trait NodeOps {
-    fn read(&mut self, _avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
-        StepResult::Nothing
+    fn read<const LOG: bool>(&mut self, _avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
+        Ok(StepResult::Nothing)
     }
-    fn compute(&mut self) -> ComputeResult {
-        StepResult::Nothing
+    fn compute<const LOG: bool>(&mut self) -> Result<ComputeResult, Error> {
+        Ok(StepResult::Nothing)
     }
-    fn write(&mut self) -> WriteResult {
-        StepResult::Nothing
+    fn write<const LOG: bool>(&mut self) -> Result<WriteResult, Error> {
+        Ok(StepResult::Nothing)
     }
-    fn advance(&mut self) -> AdvanceResult {
-        StepResult::Nothing
+    fn advance<const LOG: bool>(&mut self) -> Result<AdvanceResult, Error> {
+        Ok(StepResult::Nothing)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrokenNode;
 impl NodeOps for BrokenNode {
     // all default impls
@@ -87,15 +102,17 @@ impl Node {
         }
     }
 
-    pub fn program_node(&mut self, program_items: impl Iterator<Item=ProgramItem>) -> bool {
+    pub fn program_node(&mut self, program_items: impl Iterator<Item=ProgramItem>)
+        -> Result<bool, Error>
+    {
         match &mut self.inner {
-            NodeType::Broken(_) | NodeType::Stack(_) => false,
+            NodeType::Broken(_) | NodeType::Stack(_) => Ok(false),
             NodeType::Compute(comp) => {
-                comp.load_assembly(program_items);
-                true
+                comp.load_assembly(program_items)?;
+                Ok(true)
             }
             NodeType::Input(_) | NodeType::Output(_) | NodeType::Visualization(_) => {
-                panic!("attempted to program an I/O node somehow");
+                Err(ErrorKind::NotProgrammable(self.type_name()).into())
             }
         }
     }
@@ -112,6 +129,30 @@ impl Node {
         self.pending_output
     }
 
+    pub fn progress_state(&self) -> ProgressState {
+        let registers = match &self.inner {
+            NodeType::Compute(c) => Some((c.pc, c.acc, c.bak, c.last, c.read_result)),
+            _ => None,
+        };
+        ProgressState {
+            step: self.step,
+            pending_output: self.pending_output,
+            registers,
+        }
+    }
+
+    /// Whether running the given phase on the node would do nothing: the node is at a different
+    /// step of its cycle, or it is still blocked on a write from an earlier cycle.
+    pub fn idle(&self, phase: CycleStep) -> bool {
+        if self.step != phase {
+            return true;
+        }
+        // Stack nodes offer their top value again every cycle, so they never stay blocked.
+        phase == CycleStep::Write
+            && self.pending_output.is_some()
+            && matches!(self.inner, NodeType::Compute(_) | NodeType::Input(_))
+    }
+
     pub fn complete_write(&mut self, port: Port) {
         if let NodeType::Stack(_) = self.inner {} else {
             // Stack nodes can have a write completed while they're reading, but other node types
@@ -137,22 +178,26 @@ impl Node {
             NodeType::Visualization(_) => "visualization",
         }
     }
+}
 
-    fn inner(&mut self) -> &mut dyn NodeOps {
-        match self.inner {
-            NodeType::Broken(ref mut n) => n,
-            NodeType::Compute(ref mut n) => n,
-            NodeType::Stack(ref mut n) => n,
-            NodeType::Input(ref mut n) => n,
-            NodeType::Output(ref mut n) => n,
-            NodeType::Visualization(ref mut n) => n,
+/// Run a step on whichever kind of node this is.
+macro_rules! inner_step {
+    ($self:expr, $n:ident => $step:expr) => {
+        match $self.inner {
+            NodeType::Broken(ref mut $n) => $step,
+            NodeType::Compute(ref mut $n) => $step,
+            NodeType::Stack(ref mut $n) => $step,
+            NodeType::Input(ref mut $n) => $step,
+            NodeType::Output(ref mut $n) => $step,
+            NodeType::Visualization(ref mut $n) => $step,
         }
     }
 }
+
 macro_rules! check_step {
     ($self:expr, $exp:expr) => {
         if $self.step != $exp {
-            return StepResult::Blocked($self.step);
+            return Ok(StepResult::Blocked($self.step));
         }
     }
 }
@@ -169,24 +214,26 @@ macro_rules! advance_step {
 }
 
 impl NodeOps for Node {
-    fn read(&mut self, avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
+    fn read<const LOG: bool>(&mut self, avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
         check_step!(self, CycleStep::Read);
-        let res = self.inner().read(avail_reads);
+        let res = inner_step!(self, n => n.read::<LOG>(avail_reads))?;
         advance_step!(self, res, CycleStep::Compute);
-        res
+        Ok(res)
     }
 
-    fn compute(&mut self) -> ComputeResult {
+    fn compute<const LOG: bool>(&mut self) -> Result<ComputeResult, Error> {
         check_step!(self, CycleStep::Compute);
-        let res = self.inner().compute();
+        let res = inner_step!(self, n => n.compute::<LOG>())?;
         advance_step!(self, res, CycleStep::Write);
-        res
+        Ok(res)
     }
 
-    fn write(&mut self) -> WriteResult {
+    fn write<const LOG: bool>(&mut self) -> Result<WriteResult, Error> {
         check_step!(self, CycleStep::Write);
 
-        let res = self.inner().write();
+        let res = inner_step!(self, n => n.write::<LOG>())?;
 
         if let StepResult::IO((port, value)) = res {
             self.pending_output = Some((port, value));
@@ -195,18 +242,18 @@ impl NodeOps for Node {
         if let NodeType::Stack(_) = self.inner {
             // stack nodes should not get blocked at the Write step
             // advance it to Read (i.e. skip over Advance) instead
-            debug!("advancing Stack node to Read");
+            log_if!(LOG, debug, "advancing Stack node to Read");
             self.step = CycleStep::Read;
         }
 
         advance_step!(self, res, CycleStep::Advance);
-        res
+        Ok(res)
     }
 
-    fn advance(&mut self) -> AdvanceResult {
+    fn advance<const LOG: bool>(&mut self) -> Result<AdvanceResult, Error> {
         check_step!(self, CycleStep::Advance);
-        let res = self.inner().advance();
+        let res = inner_step!(self, n => n.advance::<LOG>())?;
         advance_step!(self, res, CycleStep::Read);
-        res
+        Ok(res)
     }
 }
@@ -0,0 +1,79 @@
+//! Per-instruction counts of how often each instruction ran and how long it stalled, and a listing
+//! of the save file annotated with them.
+
+use crate::grid::ComputeGrid;
+use crate::syntax::{PieceKind, SourceFile};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use serde::{Deserialize, Serialize};
+
+/// What one instruction of a compute node did over a run.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructionProfile {
+    /// Number of times the instruction finished.
+    pub executions: usize,
+
+    /// Cycles spent waiting for a value to read.
+    pub blocked_read: usize,
+
+    /// Cycles spent waiting for a written value to be taken.
+    pub blocked_write: usize,
+}
+
+impl InstructionProfile {
+    /// Cycles spent on the instruction, executing or stalled.
+    pub fn cycles(&self) -> usize {
+        self.executions + self.blocked_read + self.blocked_write
+    }
+}
+
+const HEADER: &str = "    EXEC    READ   WRITE";
+
+/// The save file with the profile of each instruction next to the line it is on. Lines without
+/// instructions, and nodes which weren't profiled, get no numbers.
+pub fn annotate(file: &SourceFile, grid: &ComputeGrid) -> String {
+    let mut out = String::new();
+    let blank = " ".repeat(HEADER.len());
+    writeln!(out, "{} |", HEADER).unwrap();
+
+    for line in &file.preamble {
+        writeln!(out, "{}", format!("{} | {}", blank, line.format()).trim_end()).unwrap();
+    }
+
+    // Instruction indices carry on if a node's tag appears more than once, like when loading.
+    let mut next_instruction = BTreeMap::new();
+    for node in &file.nodes {
+        writeln!(out, "{} | @{}", blank, node.id.0).unwrap();
+        let profile = grid.grid_index(node.id).and_then(|idx| grid.profile(idx));
+        let pc = next_instruction.entry(node.id).or_insert(0);
+        for line in &node.lines {
+            let instr = line.pieces.iter().any(|p| matches!(p.kind, PieceKind::Instruction(_)));
+            let counts = if instr {
+                let counts = profile.and_then(|p| p.get(*pc));
+                *pc += 1;
+                counts
+            } else {
+                None
+            };
+            let numbers = match counts {
+                Some(c) => format!("{:8}{:8}{:8}", c.executions, c.blocked_read, c.blocked_write),
+                None => blank.clone(),
+            };
+            writeln!(out, "{}", format!("{} | {}", numbers, line.format()).trim_end()).unwrap();
+        }
+    }
+    out
+}
+
+/// The instructions which took up the most cycles, as (grid node index, instruction index,
+/// profile), busiest first.
+pub fn hot_spots(grid: &ComputeGrid, count: usize) -> Vec<(usize, usize, InstructionProfile)> {
+    let mut all = (0 .. grid.node_stats().len())
+        .filter_map(|idx| grid.profile(idx).map(|p| (idx, p)))
+        .flat_map(|(idx, p)| p.iter().enumerate().map(move |(pc, c)| (idx, pc, *c)))
+        .filter(|(_, _, c)| c.cycles() != 0)
+        .collect::<Vec<_>>();
+    all.sort_by_key(|(idx, pc, c)| (std::cmp::Reverse(c.cycles()), *idx, *pc));
+    all.truncate(count);
+    all
+}
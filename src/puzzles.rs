@@ -1,6 +1,7 @@
 use crate::instr::Port;
 use crate::visualization::Color;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
 use std::collections::BTreeMap;
 
 pub const PUZZLE_WIDTH: usize = 4;
@@ -9,14 +10,62 @@ pub const INPUT_SIZE: usize = 39;
 pub const VIZ_WIDTH: usize = 30;
 pub const VIZ_HEIGHT: usize = 18;
 
-#[derive(Debug, Default)]
+/// Values for each input or output node, keyed by the grid node it's next to and which side.
+pub type Streams = BTreeMap<(usize, Port), Vec<i32>>;
+
+#[derive(Debug, Default, Clone)]
 pub struct Puzzle {
     pub name: &'static str,
     pub bad_nodes: &'static [usize],
     pub stack_nodes: &'static [usize],
-    pub inputs: BTreeMap<(usize, Port), Vec<i32>>,
-    pub outputs: BTreeMap<(usize, Port), Vec<i32>>,
+    pub inputs: Streams,
+    pub outputs: Streams,
     pub visual: BTreeMap<(usize, Port), Vec<Color>>,
+
+    /// Works out the outputs for any inputs, so they can be changed and still agree. None for
+    /// puzzles with image outputs.
+    pub expected: Option<fn(&Streams) -> Streams>,
+}
+
+impl Puzzle {
+    /// Replace the inputs, and the outputs with the ones expected for them.
+    pub fn with_inputs(&self, inputs: Streams) -> Option<Puzzle> {
+        let outputs = (self.expected?)(&inputs);
+        Some(Puzzle {
+            inputs,
+            outputs,
+            ..self.clone()
+        })
+    }
+}
+
+/// The number of every puzzle `get_puzzle` knows, in the order the game lists them, after the
+/// simulator's own debug puzzles.
+pub const PUZZLE_NUMBERS: &[&str] = &[
+    "DBG01", "DBG02",
+    "00150", "10981", "20176", "21340", "22280", "30647", "31904", "32050", "33762", "40196",
+    "41427", "42656", "43786", "50370", "51781", "52544", "53897",
+];
+
+/// The puzzle number and save slot of a save file, from its name, if it's named the way the game
+/// names them: "10981.0.txt" is the first slot of puzzle 10981. Unknown puzzles give None.
+pub fn save_file_slot(file_name: &str) -> Option<(&'static str, usize)> {
+    let mut parts = file_name.split('.');
+    let number = parts.next()?;
+    let slot = parts.next()?.parse().ok()?;
+    if parts.next() != Some("txt") || parts.next().is_some() {
+        return None;
+    }
+    let number = PUZZLE_NUMBERS.iter().find(|n| **n == number)?;
+    Some((number, slot))
+}
+
+/// The random number generator a puzzle's test inputs are made from. Seed 0 gives the inputs the
+/// simulator has always used.
+pub fn seeded_rng(seed: u64) -> ChaChaRng {
+    let mut bytes = [0; 32];
+    bytes[.. 8].copy_from_slice(&seed.to_le_bytes());
+    ChaChaRng::from_seed(bytes)
 }
 
 fn random_vec(rng: &mut impl Rng, num: usize, min: i32, max: i32) -> Vec<i32> {
@@ -29,28 +78,27 @@ pub fn get_puzzle<R: Rng + Clone + 'static>(number: &str, mut rng: R)
 {
     Some(match number {
         "DBG01" => {
-            let values = random_vec(&mut rng, INPUT_SIZE, 10, 100);
+            let inputs = btreemap! {
+                (0, Port::UP) => random_vec(&mut rng, INPUT_SIZE, 10, 100),
+            };
             Puzzle {
                 name: "[simulator debug] Connectivity Check",
-                inputs: btreemap! {
-                    (0, Port::UP) => values.clone(),
-                },
-                outputs: btreemap! {
-                    (11, Port::DOWN) => values,
-                },
+                outputs: connectivity_check(&inputs),
+                inputs,
+                expected: Some(connectivity_check),
                 ..Puzzle::default()
             }
         }
         "DBG02" => {
+            let inputs = btreemap! {
+                (0, Port::UP) => vec![1,2,3,4],
+            };
             Puzzle {
                 name: "[simulator debug] Stack Node Check",
                 stack_nodes: &[1],
-                inputs: btreemap! {
-                    (0, Port::UP) => vec![1,2,3,4],
-                },
-                outputs: btreemap! {
-                    (8, Port::DOWN) => vec![4,3,2,1],
-                },
+                outputs: stack_node_check(&inputs),
+                inputs,
+                expected: Some(stack_node_check),
                 ..Puzzle::default()
             }
         }
@@ -59,70 +107,54 @@ pub fn get_puzzle<R: Rng + Clone + 'static>(number: &str, mut rng: R)
             //let r1 = vec![51,62,16,83,61,14,35];
             let r2 = random_vec(&mut rng, INPUT_SIZE, 10, 100);
             //let r2 = vec![68,59,59,49,82,16,45];
+            let inputs = btreemap! {
+                (0, Port::UP) => r1,
+                (3, Port::UP) => r2,
+            };
             Puzzle {
                 name: "Self-Test Diagnostic",
                 bad_nodes: &[1, 5, 7, 9],
-                inputs: btreemap! {
-                    (0, Port::UP) => r1.clone(),
-                    (3, Port::UP) => r2.clone(),
-                },
-                outputs: btreemap! {
-                    (8, Port::DOWN) => r1,
-                    (11, Port::DOWN) => r2,
-                },
+                outputs: self_test_diagnostic(&inputs),
+                inputs,
+                expected: Some(self_test_diagnostic),
                 ..Puzzle::default()
             }
         }
         "10981" => {
-            let input = random_vec(&mut rng, INPUT_SIZE, 10, 100);
-            let output = input.iter().map(|n| n*2).collect();
+            let inputs = btreemap! { (1, Port::UP) => random_vec(&mut rng, INPUT_SIZE, 10, 100) };
             Puzzle {
                 name: "Signal Amplifier",
                 bad_nodes: &[3, 8],
-                inputs:  btreemap! { ( 1, Port::UP) => input },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: signal_amplifier(&inputs),
+                inputs,
+                expected: Some(signal_amplifier),
                 ..Puzzle::default()
             }
         }
         "20176" => {
             let input1 = random_vec(&mut rng, INPUT_SIZE, 10, 100);
             let input2 = random_vec(&mut rng, INPUT_SIZE, 10, 100);
-            let (output1, output2) = input1.iter()
-                .zip(&input2)
-                .map(|(a, b)| (a - b, b - a))
-                .unzip();
+            let inputs = btreemap! {
+                (1, Port::UP) => input1,
+                (2, Port::UP) => input2,
+            };
             Puzzle {
                 name: "Differential Converter",
                 bad_nodes: &[7],
-                inputs: btreemap! {
-                    (1, Port::UP) => input1,
-                    (2, Port::UP) => input2,
-                },
-                outputs: btreemap! {
-                    ( 9, Port::DOWN) => output1,
-                    (10, Port::DOWN) => output2,
-                },
+                outputs: differential_converter(&inputs),
+                inputs,
+                expected: Some(differential_converter),
                 ..Puzzle::default()
             }
         }
         "21340" => {
-            let b = |x| if x { 1 } else { 0 };
-            let input = random_vec(&mut rng, INPUT_SIZE, -2, 2);
-            let (mut output1, mut output2, mut output3) = (vec![], vec![], vec![]);
-            for n in &input {
-                output1.push(b(*n > 0));
-                output2.push(b(*n == 0));
-                output3.push(b(*n < 0));
-            }
+            let inputs = btreemap! { (0, Port::UP) => random_vec(&mut rng, INPUT_SIZE, -2, 2) };
             Puzzle {
                 name: "Signal Comparator",
                 bad_nodes: &[5, 6, 7],
-                inputs: btreemap! { (0, Port::UP) => input },
-                outputs: btreemap! {
-                    ( 9, Port::DOWN) => output1,
-                    (10, Port::DOWN) => output2,
-                    (11, Port::DOWN) => output3,
-                },
+                outputs: signal_comparator(&inputs),
+                inputs,
+                expected: Some(signal_comparator),
                 ..Puzzle::default()
             }
         }
@@ -130,154 +162,108 @@ pub fn get_puzzle<R: Rng + Clone + 'static>(number: &str, mut rng: R)
             let input1 = random_vec(&mut rng, INPUT_SIZE, -30, 0);
             let input2 = random_vec(&mut rng, INPUT_SIZE, -1, 1);
             let input3 = random_vec(&mut rng, INPUT_SIZE, 0, 30);
-            let output = input1.iter()
-                .zip(&input3)
-                .zip(&input2)
-                .map(|((a, b), which)|
-                     match *which {
-                         -1 => *a,
-                          0 => a + b,
-                          1 => *b,
-                          _ => unreachable!()
-                    })
-                .collect();
+            let inputs = btreemap! {
+                (1, Port::UP) => input1,
+                (2, Port::UP) => input2,
+                (3, Port::UP) => input3,
+            };
             Puzzle {
                 name: "Signal Multiplexer",
                 bad_nodes: &[8],
-                inputs: btreemap! {
-                    (1, Port::UP) => input1,
-                    (2, Port::UP) => input2,
-                    (3, Port::UP) => input3,
-                },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: signal_multiplexer(&inputs),
+                inputs,
+                expected: Some(signal_multiplexer),
                 ..Puzzle::default()
             }
         }
         "30647" => {
             let input1 = random_vec(&mut rng, INPUT_SIZE / 3, 10, 100);
             let input2 = random_vec(&mut rng, INPUT_SIZE / 3, 10, 100);
-            let mut output = vec![];
-            for (a, b) in input1.iter().zip(&input2) {
-                output.extend_from_slice(&[*a.min(b), *a.max(b), 0]);
-            }
+            let inputs = btreemap! {
+                (1, Port::UP) => input1,
+                (2, Port::UP) => input2,
+            };
             Puzzle {
                 name: "Sequence Generator",
                 bad_nodes: &[9],
-                inputs: btreemap! {
-                    (1, Port::UP) => input1,
-                    (2, Port::UP) => input2,
-                },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: sequence_generator(&inputs),
+                inputs,
+                expected: Some(sequence_generator),
                 ..Puzzle::default()
             }
         }
         "31904" => {
-            let mut input = vec![];
-            let mut output1 = vec![];
-            let mut output2 = vec![];
-            let mut acc = 0;
-            let mut len = 0;
             let mut zrand = random_vec(&mut rng, INPUT_SIZE, 0, 3);
             *zrand.last_mut().unwrap() = 0; // make sure it ends with 0.
-            for (r, n) in zrand.into_iter()
-                    .zip(random_vec(&mut rng, INPUT_SIZE, 10, 100).into_iter()) {
-                if r == 0 {
-                    input.push(0);
-                    output1.push(acc);
-                    output2.push(len);
-                    acc = 0;
-                    len = 0;
-                } else {
-                    input.push(n);
-                    acc += n;
-                    len += 1;
-                }
-            }
+            let input = zrand.into_iter()
+                .zip(random_vec(&mut rng, INPUT_SIZE, 10, 100))
+                .map(|(r, n)| if r == 0 { 0 } else { n })
+                .collect();
+            let inputs = btreemap! { (1, Port::UP) => input };
             Puzzle {
                 name: "Squence Counter",
                 bad_nodes: &[3],
-                inputs: btreemap! { (1, Port::UP) => input },
-                outputs: btreemap! {
-                    ( 9, Port::DOWN) => output1,
-                    (10, Port::DOWN) => output2,
-                },
+                outputs: sequence_counter(&inputs),
+                inputs,
+                expected: Some(sequence_counter),
                 ..Puzzle::default()
             }
         }
         "32050" => {
             let mut input = random_vec(&mut rng, INPUT_SIZE, -20, 40);
             input[0] = 0; // alter the first to be zero
-            let mut output = vec![0];
-            output.extend(input.iter()
-                .zip(input.iter().skip(1))
-                .map(|(a, b)| if (a - b).abs() >= 10 { 1 } else { 0 }));
+            let inputs = btreemap! { (1, Port::UP) => input };
             Puzzle {
                 name: "Signal Edge Detector",
                 bad_nodes: &[8],
-                inputs:  btreemap! { ( 1, Port::UP) => input },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: signal_edge_detector(&inputs),
+                inputs,
+                expected: Some(signal_edge_detector),
                 ..Puzzle::default()
             }
         }
         "33762" => {
-            let (inputs, output): (Vec<_>, Vec<_>) = random_vec(&mut rng, INPUT_SIZE, 0, 3)
+            // each step, one of the inputs flips between 0 and 1
+            let states = random_vec(&mut rng, INPUT_SIZE, 0, 3)
                 .into_iter()
                 .scan([0i32, 0i32, 0i32, 0i32], |last, which| {
-                    let out = if last[which as usize] == 1 {
-                        last[which as usize] = 0;
-                        // output is 0 for a high->low transition
-                        0
-                    } else {
-                        last[which as usize] = 1;
-                        // output is the number of the input which went low->high
-                        which + 1
-                    };
-                    Some((*last, out))
+                    last[which as usize] ^= 1;
+                    Some(*last)
                 })
-                .unzip();
+                .collect::<Vec<_>>();
+            let inputs = btreemap! {
+                (0, Port::UP) => states.iter().map(|v| v[0]).collect(),
+                (1, Port::UP) => states.iter().map(|v| v[1]).collect(),
+                (2, Port::UP) => states.iter().map(|v| v[2]).collect(),
+                (3, Port::UP) => states.iter().map(|v| v[3]).collect(),
+            };
             Puzzle {
                 name: "Interrupt Handler",
                 bad_nodes: &[8],
-                inputs: btreemap! {
-                    (0, Port::UP) => inputs.iter().map(|v| v[0]).collect(),
-                    (1, Port::UP) => inputs.iter().map(|v| v[1]).collect(),
-                    (2, Port::UP) => inputs.iter().map(|v| v[2]).collect(),
-                    (3, Port::UP) => inputs.iter().map(|v| v[3]).collect(),
-                },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: interrupt_handler(&inputs),
+                inputs,
+                expected: Some(interrupt_handler),
                 ..Puzzle::default()
             }
         }
         "40196" => {
-            let (input, output) = random_vec(&mut rng, INPUT_SIZE, 0, 3)
+            let input = random_vec(&mut rng, INPUT_SIZE, 0, 3)
                 .into_iter()
                 .zip(random_vec(&mut rng, INPUT_SIZE, 1, 30))
-                .scan(0, |zeroes, (zrand, nrand)|
-                    if zrand == 0 {
-                        *zeroes = 0;
-                        Some((nrand, 0))
-                    } else {
-                        *zeroes += 1;
-                        if *zeroes == 3 {
-                            *zeroes -= 1;
-                            Some((0, 1))
-                        } else {
-                            Some((0, 0))
-                        }
-                    })
-                .unzip();
+                .map(|(zrand, nrand)| if zrand == 0 { nrand } else { 0 })
+                .collect();
+            let inputs = btreemap! { (1, Port::UP) => input };
             Puzzle {
                 name: "Signal Pattern Detector",
                 bad_nodes: &[3],
-                inputs: btreemap! { (1, Port::UP) => input },
-                outputs: btreemap! { (10, Port::DOWN) => output },
+                outputs: signal_pattern_detector(&inputs),
+                inputs,
+                expected: Some(signal_pattern_detector),
                 ..Puzzle::default()
             }
         }
         "41427" => {
             let mut input = vec![];
-            let mut output1 = vec![999];
-            let mut output2 = vec![0];
             for i in 0 .. INPUT_SIZE {
                 if i > 0
                     && input.last() != Some(&0)
@@ -285,82 +271,53 @@ pub fn get_puzzle<R: Rng + Clone + 'static>(number: &str, mut rng: R)
                         || 0 == rng.gen_range(0 .. 5))
                 {
                     input.push(0);
-                    if i != INPUT_SIZE - 1 {
-                        output1.push(999);
-                        output2.push(0);
-                    }
                 } else {
-                    let value = rng.gen_range(10 .. 100);
-                    input.push(value);
-                    if Some(&value) < output1.last() {
-                        *output1.last_mut().unwrap() = value;
-                    }
-                    if Some(&value) > output2.last() {
-                        *output2.last_mut().unwrap() = value;
-                    }
+                    input.push(rng.gen_range(10 .. 100));
                 }
             }
-
+            let inputs = btreemap! { (1, Port::UP) => input };
             Puzzle {
                 name: "Sequence Peak Detector",
                 bad_nodes: &[7],
-                inputs: btreemap! {
-                    (1, Port::UP) => input,
-                },
-                outputs: btreemap! {
-                    ( 9, Port::DOWN) => output1,
-                    (10, Port::DOWN) => output2,
-                },
+                outputs: sequence_peak_detector(&inputs),
+                inputs,
+                expected: Some(sequence_peak_detector),
                 ..Puzzle::default()
             }
         },
         "42656" => {
             let mut input = random_vec(&mut rng, INPUT_SIZE, 10, 100);
-            let mut output = vec![];
-            let mut buf: Vec<i32> = vec![];
-            for (n, random) in input.iter_mut().zip(random_vec(&mut rng, INPUT_SIZE, 0, 5).into_iter()) {
+            for (n, random) in input.iter_mut().zip(random_vec(&mut rng, INPUT_SIZE, 0, 5)) {
                 if random == 0 {
                     *n = 0;
-                    output.extend(buf.drain(..).rev());
-                    output.push(0);
-                } else {
-                    buf.push(*n);
                 }
             }
-            buf.pop();
-            output.extend(buf.drain(..).rev());
-            output.push(0);
             *input.last_mut().unwrap() = 0;
+            let inputs = btreemap! { (1, Port::UP) => input };
             Puzzle {
                 name: "Sequence Reverser",
                 bad_nodes: &[8],
                 stack_nodes: &[2, 9],
-                inputs: btreemap! {
-                    (1, Port::UP) => input,
-                },
-                outputs: btreemap! {
-                    (10, Port::DOWN) => output,
-                },
+                outputs: sequence_reverser(&inputs),
+                inputs,
+                expected: Some(sequence_reverser),
                 ..Puzzle::default()
             }
         }
         "43786" => {
             let input1 = random_vec(&mut rng, INPUT_SIZE, 0, 9);
             let input2 = random_vec(&mut rng, INPUT_SIZE, 0, 9);
-            let output = input1.iter().zip(input2.iter())
-                .map(|(a, b)| a * b)
-                .collect();
+            let inputs = btreemap! {
+                (1, Port::UP) => input1,
+                (2, Port::UP) => input2,
+            };
             Puzzle {
                 name: "Signal Multiplier",
                 bad_nodes: &[8],
                 stack_nodes: &[4, 7],
-                inputs: btreemap! {
-                    (1, Port::UP) => input1,
-                    (2, Port::UP) => input2,
-                },
-                outputs: btreemap! {
-                    (10, Port::DOWN) => output,
-                },
+                outputs: signal_multiplier(&inputs),
+                inputs,
+                expected: Some(signal_multiplier),
                 ..Puzzle::default()
             }
         }
@@ -458,3 +415,188 @@ pub fn get_puzzle<R: Rng + Clone + 'static>(number: &str, mut rng: R)
         _ => return None
     })
 }
+
+// The expected outputs of each puzzle, given its inputs.
+
+fn connectivity_check(inputs: &Streams) -> Streams {
+    btreemap! { (11, Port::DOWN) => inputs[&(0, Port::UP)].clone() }
+}
+
+fn stack_node_check(inputs: &Streams) -> Streams {
+    btreemap! { (8, Port::DOWN) => inputs[&(0, Port::UP)].iter().rev().copied().collect() }
+}
+
+fn self_test_diagnostic(inputs: &Streams) -> Streams {
+    btreemap! {
+        (8, Port::DOWN) => inputs[&(0, Port::UP)].clone(),
+        (11, Port::DOWN) => inputs[&(3, Port::UP)].clone(),
+    }
+}
+
+fn signal_amplifier(inputs: &Streams) -> Streams {
+    btreemap! { (10, Port::DOWN) => inputs[&(1, Port::UP)].iter().map(|n| n*2).collect() }
+}
+
+fn differential_converter(inputs: &Streams) -> Streams {
+    let (output1, output2) = inputs[&(1, Port::UP)].iter()
+        .zip(&inputs[&(2, Port::UP)])
+        .map(|(a, b)| (a - b, b - a))
+        .unzip();
+    btreemap! {
+        ( 9, Port::DOWN) => output1,
+        (10, Port::DOWN) => output2,
+    }
+}
+
+fn signal_comparator(inputs: &Streams) -> Streams {
+    let b = |x| if x { 1 } else { 0 };
+    let (mut output1, mut output2, mut output3) = (vec![], vec![], vec![]);
+    for n in &inputs[&(0, Port::UP)] {
+        output1.push(b(*n > 0));
+        output2.push(b(*n == 0));
+        output3.push(b(*n < 0));
+    }
+    btreemap! {
+        ( 9, Port::DOWN) => output1,
+        (10, Port::DOWN) => output2,
+        (11, Port::DOWN) => output3,
+    }
+}
+
+fn signal_multiplexer(inputs: &Streams) -> Streams {
+    let output = inputs[&(1, Port::UP)].iter()
+        .zip(&inputs[&(3, Port::UP)])
+        .zip(&inputs[&(2, Port::UP)])
+        .map(|((a, b), which)|
+             match which.signum() {
+                 -1 => *a,
+                  0 => a + b,
+                  _ => *b,
+            })
+        .collect();
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn sequence_generator(inputs: &Streams) -> Streams {
+    let mut output = vec![];
+    for (a, b) in inputs[&(1, Port::UP)].iter().zip(&inputs[&(2, Port::UP)]) {
+        output.extend_from_slice(&[*a.min(b), *a.max(b), 0]);
+    }
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn sequence_counter(inputs: &Streams) -> Streams {
+    let mut output1 = vec![];
+    let mut output2 = vec![];
+    let mut acc = 0;
+    let mut len = 0;
+    for n in &inputs[&(1, Port::UP)] {
+        if *n == 0 {
+            output1.push(acc);
+            output2.push(len);
+            acc = 0;
+            len = 0;
+        } else {
+            acc += n;
+            len += 1;
+        }
+    }
+    btreemap! {
+        ( 9, Port::DOWN) => output1,
+        (10, Port::DOWN) => output2,
+    }
+}
+
+fn signal_edge_detector(inputs: &Streams) -> Streams {
+    let input = &inputs[&(1, Port::UP)];
+    let output = input.iter()
+        .enumerate()
+        .map(|(i, b)| match i.checked_sub(1) {
+            Some(prev) if (input[prev] - b).abs() >= 10 => 1,
+            _ => 0,
+        })
+        .collect();
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn interrupt_handler(inputs: &Streams) -> Streams {
+    let lines = (0 .. 4).map(|idx| &inputs[&(idx, Port::UP)]).collect::<Vec<_>>();
+    let mut last = [0; 4];
+    let mut output = vec![];
+    for step in 0 .. lines[0].len() {
+        // the number of the first input which went low->high, or 0 if none did
+        let mut out = 0;
+        for (which, line) in lines.iter().enumerate().rev() {
+            if last[which] == 0 && line[step] != 0 {
+                out = which as i32 + 1;
+            }
+            last[which] = line[step];
+        }
+        output.push(out);
+    }
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn signal_pattern_detector(inputs: &Streams) -> Streams {
+    let output = inputs[&(1, Port::UP)].iter()
+        .scan(0, |zeroes, n| {
+            if *n == 0 {
+                *zeroes += 1;
+            } else {
+                *zeroes = 0;
+            }
+            Some(if *zeroes >= 3 { 1 } else { 0 })
+        })
+        .collect();
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn sequence_peak_detector(inputs: &Streams) -> Streams {
+    let input = &inputs[&(1, Port::UP)];
+    let mut output1 = vec![999];
+    let mut output2 = vec![0];
+    for (i, value) in input.iter().enumerate() {
+        if *value == 0 {
+            if i != input.len() - 1 {
+                output1.push(999);
+                output2.push(0);
+            }
+        } else {
+            if Some(value) < output1.last() {
+                *output1.last_mut().unwrap() = *value;
+            }
+            if Some(value) > output2.last() {
+                *output2.last_mut().unwrap() = *value;
+            }
+        }
+    }
+    btreemap! {
+        ( 9, Port::DOWN) => output1,
+        (10, Port::DOWN) => output2,
+    }
+}
+
+/// Each sequence reversed, followed by the 0 that ended it. There are exactly as many outputs as
+/// inputs, even when the last random value picked a 0 for the end of the input, which it always
+/// ends with anyway.
+fn sequence_reverser(inputs: &Streams) -> Streams {
+    let mut output = vec![];
+    let mut buf = vec![];
+    for n in &inputs[&(1, Port::UP)] {
+        if *n == 0 {
+            output.extend(buf.drain(..).rev());
+            output.push(0);
+        } else {
+            buf.push(*n);
+        }
+    }
+    btreemap! { (10, Port::DOWN) => output }
+}
+
+fn signal_multiplier(inputs: &Streams) -> Streams {
+    let output = inputs[&(1, Port::UP)].iter()
+        .zip(&inputs[&(2, Port::UP)])
+        .map(|(a, b)| a * b)
+        .collect();
+    btreemap! { (10, Port::DOWN) => output }
+}
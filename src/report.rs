@@ -0,0 +1,201 @@
+//! The result of running a solution, in a form that can be printed as JSON for other tools to read.
+//! Field names here are relied on by scripts, so they should not be renamed.
+
+use crate::grid::{ComputeGrid, Mismatch, Outcome};
+use serde::Serialize;
+
+/// Everything about one run of a solution against one puzzle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RunReport {
+    /// The puzzle number, like "10981".
+    pub puzzle: String,
+
+    /// The save slot, if the save file is named like the game's, like 0 for "10981.0.txt".
+    pub slot: Option<usize>,
+
+    /// The puzzle's name, if the number is a known puzzle.
+    pub name: Option<String>,
+
+    /// The seed the puzzle's test inputs were made from.
+    pub seed: u64,
+
+    pub passed: bool,
+
+    /// Cycles run before the solution passed or failed, if it got to run.
+    pub cycles: Option<usize>,
+
+    pub nodes: usize,
+
+    pub instructions: usize,
+
+    /// Why the solution didn't pass.
+    pub failure: Option<Failure>,
+}
+
+/// Why a solution didn't pass.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Failure {
+    /// The save file couldn't be read or loaded into the grid, or the puzzle is unknown.
+    Load { errors: Vec<String> },
+
+    /// An output node received a wrong value. Has which value and where, unless it was an image
+    /// that was drawn wrong.
+    Incorrect { mismatch: Option<Mismatch> },
+
+    /// No node could make progress. Lists the nodes stuck waiting on I/O.
+    Deadlock { blocked: Vec<String> },
+
+    /// A node hit an error while running, like a Halt and Catch Fire instruction.
+    Error { message: String },
+}
+
+impl RunReport {
+    pub fn new(puzzle: &str, seed: u64) -> RunReport {
+        RunReport {
+            puzzle: puzzle.to_owned(),
+            slot: None,
+            name: None,
+            seed,
+            passed: false,
+            cycles: None,
+            nodes: 0,
+            instructions: 0,
+            failure: None,
+        }
+    }
+
+    /// Record the size of the program loaded into the grid.
+    pub fn loaded(&mut self, grid: &ComputeGrid) {
+        self.nodes = grid.count_programmed_nodes();
+        self.instructions = grid.count_instructions();
+    }
+
+    /// Record how a run ended.
+    pub fn finished(&mut self, outcome: &Outcome, cycles: usize) {
+        self.cycles = Some(cycles);
+        self.passed = *outcome == Outcome::Correct;
+        self.failure = match outcome {
+            Outcome::Correct => None,
+            Outcome::Incorrect(mismatch) => Some(Failure::Incorrect { mismatch: mismatch.clone() }),
+            Outcome::Deadlock(blocked) => Some(Failure::Deadlock {
+                blocked: blocked.iter().map(ToString::to_string).collect(),
+            }),
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report can't fail to serialize")
+    }
+}
+
+/// The results of running a solution against several test sets, each made from a different seed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TestSetsReport {
+    pub puzzle: String,
+
+    pub slot: Option<usize>,
+
+    pub name: Option<String>,
+
+    /// Whether the solution passed every set.
+    pub passed: bool,
+
+    /// The most cycles any set took, if they all passed.
+    pub worst_cycles: Option<usize>,
+
+    /// The average cycles over all the sets, if they all passed.
+    pub average_cycles: Option<f64>,
+
+    pub nodes: usize,
+
+    pub instructions: usize,
+
+    pub sets: Vec<RunReport>,
+}
+
+impl TestSetsReport {
+    /// Sum up the reports of each set. There has to be at least one.
+    pub fn new(sets: Vec<RunReport>) -> TestSetsReport {
+        let first = &sets[0];
+        let passed = sets.iter().all(|set| set.passed);
+        let cycles = sets.iter().filter_map(|set| set.cycles);
+        TestSetsReport {
+            puzzle: first.puzzle.clone(),
+            slot: first.slot,
+            name: first.name.clone(),
+            passed,
+            worst_cycles: cycles.clone().max().filter(|_| passed),
+            average_cycles: Some(cycles.sum::<usize>() as f64 / sets.len() as f64)
+                .filter(|_| passed),
+            nodes: first.nodes,
+            instructions: first.instructions,
+            sets,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report can't fail to serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_fields() {
+        let mut report = RunReport::new("10981", 0);
+        report.slot = Some(3);
+        report.name = Some("Signal Amplifier".to_owned());
+        report.finished(&Outcome::Deadlock(vec![]), 12);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "puzzle": "10981",
+                "slot": 3,
+                "name": "Signal Amplifier",
+                "seed": 0,
+                "passed": false,
+                "cycles": 12,
+                "nodes": 0,
+                "instructions": 0,
+                "failure": { "kind": "deadlock", "blocked": [] },
+            }),
+            json);
+
+        report.failure = Some(Failure::Error { message: "oops".to_owned() });
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(serde_json::json!({ "kind": "error", "message": "oops" }), json["failure"]);
+
+        report.finished(&Outcome::Incorrect(None), 12);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(serde_json::json!({ "kind": "incorrect", "mismatch": null }), json["failure"]);
+    }
+
+    #[test]
+    fn test_sets() {
+        let set = |seed, outcome, cycles| {
+            let mut report = RunReport::new("10981", seed);
+            report.finished(&outcome, cycles);
+            report
+        };
+        let sets = TestSetsReport::new(vec![
+            set(0, Outcome::Correct, 100),
+            set(1, Outcome::Correct, 120),
+            set(2, Outcome::Correct, 101),
+        ]);
+        assert!(sets.passed);
+        assert_eq!(Some(120), sets.worst_cycles);
+        assert_eq!(Some(107.), sets.average_cycles);
+
+        let sets = TestSetsReport::new(vec![
+            set(0, Outcome::Correct, 100),
+            set(1, Outcome::Incorrect(None), 50),
+        ]);
+        assert!(!sets.passed);
+        assert_eq!(None, sets.worst_cycles);
+        assert_eq!(None, sets.average_cycles);
+        assert_eq!(2, sets.sets.len());
+    }
+}
@@ -0,0 +1,128 @@
+//! Saving the complete state of a grid partway through a run, to go back to later, and reading and
+//! writing it as a file so long runs can be picked up again.
+
+use crate::grid::ComputeGrid;
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// Changed whenever the grid's state changes shape, so old files aren't read as something else.
+const FORMAT_VERSION: u32 = 2;
+
+/// Everything about a grid at one point in a run: every node's program, registers, and pending
+/// I/O, the input and output streams, which nodes are blocked and asleep, and the stats, profile,
+/// and transfer log being kept. Restoring it carries on exactly as the run would have.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    grid: ComputeGrid,
+}
+
+impl Snapshot {
+    pub(crate) fn new(grid: ComputeGrid) -> Snapshot {
+        Snapshot {
+            version: FORMAT_VERSION,
+            grid,
+        }
+    }
+
+    pub(crate) fn grid(&self) -> &ComputeGrid {
+        &self.grid
+    }
+
+    /// Number of cycles the grid had completed.
+    pub fn cycle(&self) -> usize {
+        self.grid.cycle()
+    }
+
+    /// A new grid in the state of the snapshot.
+    pub fn to_grid(&self) -> ComputeGrid {
+        self.grid.clone()
+    }
+
+    /// Write the snapshot as JSON.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        serde_json::to_writer(out, self).map_err(io::Error::from)
+    }
+
+    /// Read a snapshot written by `write`.
+    pub fn read(input: impl Read) -> io::Result<Snapshot> {
+        let snapshot: Snapshot = serde_json::from_reader(input)?;
+        if snapshot.version != FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("snapshot is format version {}, but only version {} can be read",
+                    snapshot.version, FORMAT_VERSION)));
+        }
+        snapshot.grid.check()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                format!("snapshot doesn't fit together: {}", e)))?;
+        Ok(snapshot)
+    }
+}
+
+/// JSON object keys have to be strings, so maps with other keys are written as lists of pairs.
+pub(crate) mod pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(map: &BTreeMap<K, V>, s: S)
+        -> Result<S::Ok, S::Error>
+    {
+        s.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(d: D) -> Result<BTreeMap<K, V>, D::Error>
+        where K: Deserialize<'de> + Ord, V: Deserialize<'de>, D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(d).map(|pairs| pairs.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_version() {
+        let puzzle = crate::puzzles::get_puzzle("DBG01", crate::puzzles::seeded_rng(0)).unwrap();
+        let snapshot = ComputeGrid::from_puzzle(puzzle).snapshot();
+        let mut file = vec![];
+        snapshot.write(&mut file).unwrap();
+        assert_eq!(0, Snapshot::read(&file[..]).unwrap().cycle());
+
+        let mut json: serde_json::Value = serde_json::from_slice(&file).unwrap();
+        json["version"] = serde_json::json!(FORMAT_VERSION + 1);
+        let err = Snapshot::read(json.to_string().as_bytes()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_inconsistent() {
+        let puzzle = crate::puzzles::get_puzzle("DBG01", crate::puzzles::seeded_rng(0)).unwrap();
+        let mut grid = ComputeGrid::from_puzzle(puzzle);
+        let program = crate::assembly::program_items(b"L: MOV UP, DOWN\nJMP L").unwrap();
+        grid.program_node(0, program).unwrap();
+        grid.step().unwrap();
+        let mut file = vec![];
+        grid.snapshot().write(&mut file).unwrap();
+        let json: Value = serde_json::from_slice(&file).unwrap();
+        Snapshot::read(json.to_string().as_bytes()).unwrap();
+
+        let corruptions: &[fn(&mut Value)] = &[
+            |grid| { grid["asleep"].as_array_mut().unwrap().pop(); },
+            |grid| { grid["read_blocked"].as_array_mut().unwrap().pop(); },
+            |grid| { grid["stats"].as_array_mut().unwrap().pop(); },
+            |grid| { grid["nodes"][0]["inner"]["Compute"]["pc"] = json!(2); },
+            |grid| { grid["nodes"][0]["inner"]["Compute"]["ops"][1]["JMP"] = json!(5); },
+            |grid| { grid["nodes"][0]["inner"]["Compute"]["ops"].as_array_mut().unwrap().pop(); },
+        ];
+        for (i, corrupt) in corruptions.iter().enumerate() {
+            let mut json = json.clone();
+            corrupt(&mut json["grid"]);
+            let err = Snapshot::read(json.to_string().as_bytes()).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind(), "corruption {}: {}", i, err);
+            assert!(err.to_string().starts_with("snapshot doesn't fit together"), "{}", err);
+        }
+    }
+}
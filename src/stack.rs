@@ -1,35 +1,46 @@
+use crate::error::Error;
 use crate::node::{StepResult, ReadResult, WriteResult, AdvanceResult, NodeOps};
 use crate::instr::Port;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StackNode {
     values: Vec<i32>,
 }
 
+impl StackNode {
+    /// The values on the stack, from bottom to top.
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+}
+
 impl NodeOps for StackNode {
-    fn read(&mut self, avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
-        if let Some((src_port, value)) = avail_reads.get_mut(0).take() {
+    fn read<const LOG: bool>(&mut self, avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
+        if let Some((src_port, value)) = avail_reads.get_mut(0) {
             let value = value.take().unwrap();
-            debug!("stack node read {} from {}", value, src_port);
+            log_if!(LOG, debug, "stack node read {} from {}", value, src_port);
             self.values.push(value);
-            return StepResult::Okay
+            return Ok(StepResult::Okay);
         }
-        StepResult::Nothing // don't return IO because we don't want to get stuck here
+        Ok(StepResult::Nothing) // don't return IO because we don't want to get stuck here
     }
 
     // default impl for compute
 
-    fn write(&mut self) -> WriteResult {
+    fn write<const LOG: bool>(&mut self) -> Result<WriteResult, Error> {
         if let Some(value) = self.values.last() {
-            StepResult::IO((Port::ANY, *value))
+            Ok(StepResult::IO((Port::ANY, *value)))
         } else {
-            StepResult::Nothing
+            Ok(StepResult::Nothing)
         }
     }
 
-    fn advance(&mut self) -> AdvanceResult {
+    fn advance<const LOG: bool>(&mut self) -> Result<AdvanceResult, Error> {
         self.values.pop();
-        StepResult::Okay
+        Ok(StepResult::Okay)
     }
 
 }
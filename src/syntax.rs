@@ -0,0 +1,425 @@
+//! A lossless syntax tree for save files, which keeps comments, blank lines, spacing and where
+//! labels are placed, so that a file can be rewritten without losing anything the player wrote.
+
+use crate::assembly::{self, Nodes, ParseError, PResult};
+use crate::instr::*;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::space0;
+use nom::combinator::{map, value};
+use nom::sequence::terminated;
+use std::fmt::{self, Display, Formatter};
+
+/// A whole save file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    /// Lines before the first node tag. These can only have comments.
+    pub preamble: Vec<SourceLine>,
+
+    pub nodes: Vec<SourceNode>,
+}
+
+/// A node tag and the lines of code after it, up to the next node tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceNode {
+    pub id: SaveFileNodeId,
+
+    /// Line number of the node tag, starting at 1.
+    pub line: usize,
+
+    /// The text of the node tag line, including its line ending.
+    pub tag: String,
+
+    pub lines: Vec<SourceLine>,
+}
+
+/// One line of code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// Line number, starting at 1.
+    pub line: usize,
+
+    pub pieces: Vec<Piece>,
+
+    /// Whitespace after the last piece.
+    pub trailing: String,
+
+    /// "\n", "\r\n", or empty for a last line with no line ending.
+    pub line_ending: String,
+}
+
+/// A breakpoint, label, instruction or comment, with the whitespace before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    /// Whitespace before the piece.
+    pub space: String,
+
+    /// Column the piece starts at in the original input, starting at 1.
+    pub column: usize,
+
+    pub kind: PieceKind,
+
+    /// The text of the piece as it was written.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PieceKind {
+    Breakpoint,
+    Label(String),
+    Instruction(Instruction),
+    /// The text of the comment, starting with the '#', is in the piece's text.
+    Comment,
+}
+
+impl Piece {
+    /// The program item this piece stands for, if it isn't a comment.
+    pub fn program_item(&self) -> Option<ProgramItem> {
+        match &self.kind {
+            PieceKind::Breakpoint => Some(ProgramItem::Breakpoint),
+            PieceKind::Label(label) => Some(ProgramItem::Label(label.clone())),
+            PieceKind::Instruction(instr) => Some(ProgramItem::Instruction(instr.clone())),
+            PieceKind::Comment => None,
+        }
+    }
+}
+
+fn item(input: &[u8]) -> PResult<'_, PieceKind> {
+    alt(
+        (
+            map(terminated(assembly::label, tag(b":")),
+                |label| PieceKind::Label(label.to_owned())),
+            map(assembly::instruction, PieceKind::Instruction),
+            value(PieceKind::Breakpoint, tag(b"!")),
+        )
+    )(input)
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Split a line into its content and its line ending.
+fn split_line_ending(line: &[u8]) -> (&[u8], &[u8]) {
+    let len = if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    };
+    line.split_at(line.len() - len)
+}
+
+/// Parse one line into pieces. `line` is the input from the start of the line, and `len` is the
+/// length of the line without its line ending. Outside of a node, only comments are allowed.
+fn parse_line(
+    input: &[u8],
+    line: &[u8],
+    len: usize,
+    ending: &[u8],
+    node: Option<SaveFileNodeId>,
+) -> Result<SourceLine, ParseError> {
+    // Parsers get the rest of the input rather than just the line, so errors know where they are.
+    let end = line.len() - len;
+    let number = assembly::line_col(input, input.len() - line.len()).0;
+    let mut pieces = vec![];
+    let mut rest = line;
+    let mut after_instruction = false;
+    loop {
+        let (start, space) = space0::<_, nom::error::Error<&[u8]>>(rest).expect("can't fail");
+        if start.len() == end {
+            return Ok(SourceLine {
+                line: number,
+                pieces,
+                trailing: text(space),
+                line_ending: text(ending),
+            });
+        }
+
+        let (next, kind) = if start[0] == b'#' {
+            (&start[start.len() - end ..], PieceKind::Comment)
+        } else if node.is_none() {
+            return Err(ParseError::new(input, start, node, "node tag like @0"));
+        } else if after_instruction {
+            return Err(ParseError::new(input, start, node, "end of line or comment"));
+        } else {
+            item(start).map_err(|e| {
+                let (pos, expected) = assembly::nom_error(e);
+                ParseError::new(input, pos, node, expected)
+            })?
+        };
+
+        after_instruction = matches!(kind, PieceKind::Instruction(_));
+        pieces.push(Piece {
+            space: text(space),
+            column: line.len() - start.len() + 1,
+            kind,
+            text: text(&start[.. start.len() - next.len()]),
+        });
+        rest = next;
+    }
+}
+
+impl SourceFile {
+    /// Parse a save file, returning all the errors in it if there are any.
+    pub fn parse(input: &[u8]) -> Result<SourceFile, Vec<ParseError>> {
+        let mut file = SourceFile {
+            preamble: vec![],
+            nodes: vec![],
+        };
+        let mut errors = vec![];
+        let mut offset = 0;
+        for full_line in input.split_inclusive(|b| *b == b'\n') {
+            let (line, ending) = split_line_ending(full_line);
+            let line_start = &input[offset ..];
+            offset += full_line.len();
+
+            let indent = line.iter().take_while(|b| **b == b' ' || **b == b'\t').count();
+            if let Ok((_, id)) = assembly::node_tag(&full_line[indent ..]) {
+                file.nodes.push(SourceNode {
+                    id,
+                    line: assembly::line_col(input, input.len() - line_start.len()).0,
+                    tag: text(full_line),
+                    lines: vec![],
+                });
+                continue;
+            }
+
+            let node = file.nodes.last_mut();
+            let id = node.as_ref().map(|node| node.id);
+            match parse_line(input, line_start, line.len(), ending, id) {
+                Ok(parsed) => match node {
+                    Some(node) => node.lines.push(parsed),
+                    None => file.preamble.push(parsed),
+                },
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(file)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The program of each node, the same as `assembly::parse_save_file` gives.
+    pub fn nodes(&self) -> Nodes {
+        let mut nodes = Nodes::new();
+        for node in &self.nodes {
+            nodes.entry(node.id).or_default().extend(node.program_items());
+        }
+        nodes
+    }
+
+    /// Lay the file out with the same spacing everywhere, like the game does, while keeping
+    /// comments, blank lines, and which line each label is on.
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        let preamble = format_lines(&self.preamble);
+        if !preamble.is_empty() {
+            out += &preamble;
+            out.push('\n');
+        }
+        for node in &self.nodes {
+            out += &format!("@{}\n", node.id.0);
+            out += &format_lines(&node.lines);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Format lines, dropping any blank lines at the end.
+fn format_lines(lines: &[SourceLine]) -> String {
+    let end = lines.iter()
+        .rposition(|line| !line.pieces.is_empty())
+        .map_or(0, |i| i + 1);
+    lines[.. end].iter()
+        .map(|line| line.format() + "\n")
+        .collect()
+}
+
+impl SourceNode {
+    pub fn program_items(&self) -> impl Iterator<Item = ProgramItem> + '_ {
+        self.lines.iter()
+            .flat_map(|line| line.pieces.iter())
+            .filter_map(Piece::program_item)
+    }
+
+    /// The node's title, from the first comment starting with "##", which is how the game names
+    /// nodes.
+    pub fn title(&self) -> Option<&str> {
+        self.lines.iter()
+            .flat_map(|line| line.pieces.iter())
+            .find_map(|piece| match piece.kind {
+                PieceKind::Comment => piece.text.strip_prefix("##"),
+                _ => None,
+            })
+            .map(str::trim)
+    }
+}
+
+impl SourceLine {
+    /// The line with its pieces separated by single spaces, except after a breakpoint, and no
+    /// trailing whitespace or line ending.
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        let mut prev: Option<&PieceKind> = None;
+        for piece in &self.pieces {
+            match prev {
+                None | Some(PieceKind::Breakpoint) => (),
+                Some(_) => out.push(' '),
+            }
+            match &piece.kind {
+                PieceKind::Breakpoint => out.push('!'),
+                PieceKind::Label(label) => out += &format!("{}:", label),
+                PieceKind::Instruction(instr) => out += &instr.to_string(),
+                PieceKind::Comment => out += piece.text.trim_end(),
+            }
+            prev = Some(&piece.kind);
+        }
+        out
+    }
+}
+
+impl Display for SourceFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for line in &self.preamble {
+            write!(f, "{}", line)?;
+        }
+        for node in &self.nodes {
+            write!(f, "{}", node)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SourceNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.tag)?;
+        for line in &self.lines {
+            write!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SourceLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for piece in &self.pieces {
+            write!(f, "{}{}", piece.space, piece.text)?;
+        }
+        write!(f, "{}{}", self.trailing, self.line_ending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &[u8] = b"# solution notes\r\n\
+        @0\r\n\
+        ##ADDER\r\n\
+        START:MOV  UP ,ACC   # read\r\n\
+        \tL1: L2:\r\n\
+        !ADD\t1 \r\n\
+        \r\n\
+        ! JMP START\r\n\
+        \r\n\
+        @1\n\
+        NOP\n\
+        @2\n\
+        # nothing here\n\
+        MOV UP, DOWN";
+
+    #[test]
+    fn test_round_trip() {
+        let file = SourceFile::parse(INPUT).unwrap();
+        assert_eq!(INPUT, file.to_string().as_bytes());
+        assert_eq!(assembly::parse_save_file(INPUT).unwrap(), file.nodes());
+        assert_eq!(3, file.nodes.len());
+        assert_eq!(1, file.preamble.len());
+        assert_eq!(7, file.nodes[0].lines.len());
+        assert_eq!(10, file.nodes[1].line);
+    }
+
+    #[test]
+    fn test_pieces() {
+        let file = SourceFile::parse(INPUT).unwrap();
+        let line = &file.nodes[0].lines[1];
+        assert_eq!(4, line.line);
+        assert_eq!(
+            vec![
+                (1, PieceKind::Label("START".to_owned()), "START:"),
+                (7, PieceKind::Instruction(Instruction::MOV(Src::Port(Port::UP),
+                    Dst::Register(Register::ACC))), "MOV  UP ,ACC"),
+                (22, PieceKind::Comment, "# read"),
+            ],
+            line.pieces.iter()
+                .map(|p| (p.column, p.kind.clone(), p.text.as_str()))
+                .collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_format() {
+        let file = SourceFile::parse(INPUT).unwrap();
+        assert_eq!(
+            "# solution notes\n\
+            \n\
+            @0\n\
+            ##ADDER\n\
+            START: MOV UP, ACC # read\n\
+            L1: L2:\n\
+            !ADD 1\n\
+            \n\
+            !JMP START\n\
+            \n\
+            @1\n\
+            NOP\n\
+            \n\
+            @2\n\
+            # nothing here\n\
+            MOV UP, DOWN\n\
+            \n",
+            file.format());
+    }
+
+    #[test]
+    fn test_title() {
+        let file = SourceFile::parse(INPUT).unwrap();
+        assert_eq!(Some("ADDER"), file.nodes[0].title());
+        assert_eq!(None, file.nodes[1].title());
+        assert_eq!(None, file.nodes[2].title());
+    }
+
+    #[test]
+    fn test_errors() {
+        let input = b"NOP\n@0\nADD 1 !\nMOV UP\nNOP\n";
+        let errors = SourceFile::parse(input).unwrap_err();
+        assert_eq!(assembly::parse_save_file(input).unwrap_err(), errors);
+    }
+
+    #[test]
+    fn test_same_as_assembly() {
+        // both parsers take the same files, and find the same errors in the ones they don't
+        let inputs: &[&[u8]] = &[
+            b"@0\nA: !NOP\n",
+            b"@0\nA:!\n",
+            b"@0\n! A: NOP\n",
+            b"@0\nNOP !\n",
+            b"@0\nA:@1\nNOP\n",
+            b"@0\n!@1\n",
+            b"@0\nNOP @1\n",
+            b"@0\n  @1\nNOP\n",
+            b"@0\nADD 1000\n",
+            b"@0\nMOV UP,\n",
+        ];
+        for input in inputs {
+            let parsed = SourceFile::parse(input).map(|file| file.nodes());
+            assert_eq!(assembly::parse_save_file(input), parsed,
+                "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! A record of everything that happens in the grid, written as one line of JSON per cycle, for
+//! looking through a run with other tools.
+
+use crate::grid::{ComputeGrid, NodeLocation, Outcome, Transfer};
+use crate::instr::Port;
+use crate::io::VerifyState;
+use crate::node::NodeType;
+
+use serde_json::{json, Value};
+use std::io::{self, Write};
+
+/// Writes a line for each cycle the grid runs. Each line has the cycle number, the registers and
+/// step of every programmed compute node, the values passed between grid nodes, the values taken
+/// from inputs, and the values given to outputs, with whether they were what the output expected.
+/// The line for the last cycle also has how the run ended.
+pub struct TraceWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Start tracing the grid, which should not have run yet.
+    pub fn new(out: W, grid: &mut ComputeGrid) -> TraceWriter<W> {
+        grid.enable_transfers();
+        TraceWriter { out }
+    }
+
+    /// Write the line for the cycle the grid just finished, which passed the given values between
+    /// nodes.
+    pub fn write_cycle(&mut self, grid: &ComputeGrid, transfers: &[Transfer],
+        outcome: Option<&Outcome>) -> io::Result<()>
+    {
+        let line = cycle_json(grid, transfers, outcome);
+        writeln!(self.out, "{}", line)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+fn cycle_json(grid: &ComputeGrid, transfers: &[Transfer], outcome: Option<&Outcome>) -> Value {
+    let mut nodes = vec![];
+    for idx in 0 .. {
+        let node = match grid.node(idx) {
+            Some(node) => node,
+            None => break,
+        };
+        if let NodeType::Compute(c) = &node.inner {
+            if c.instructions.is_empty() {
+                continue;
+            }
+            nodes.push(json!({
+                "node": idx,
+                "pc": c.pc,
+                "acc": c.acc,
+                "bak": c.bak,
+                "last": if c.last == Port::LAST { None } else { Some(c.last.to_string()) },
+                "step": node.step.to_string(),
+            }));
+        }
+    }
+
+    let mut passed = vec![];
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    for transfer in transfers {
+        match (external_type(grid, transfer.from), external_type(grid, transfer.to)) {
+            (Some(NodeType::Input(_)), _) => inputs.push(json!({
+                "from": location_json(transfer.from),
+                "to": location_json(transfer.to),
+                "value": transfer.value,
+            })),
+            (_, Some(NodeType::Output(output))) => outputs.push(json!({
+                "from": location_json(transfer.from),
+                "to": location_json(transfer.to),
+                "value": transfer.value,
+                "correct": !matches!(output.verified(), VerifyState::Failed),
+            })),
+            _ => passed.push(transfer_json(transfer)),
+        }
+    }
+
+    let mut line = json!({
+        "cycle": grid.cycle(),
+        "nodes": nodes,
+        "transfers": passed,
+        "inputs": inputs,
+        "outputs": outputs,
+    });
+    if let Some(outcome) = outcome {
+        line["outcome"] = json!(outcome.to_string());
+    }
+    line
+}
+
+/// The type of the I/O node at a location, if it is one.
+fn external_type(grid: &ComputeGrid, location: NodeLocation) -> Option<&NodeType> {
+    grid.external_nodes()
+        .find(|(l, _)| *l == location)
+        .map(|(_, node)| &node.inner)
+}
+
+fn transfer_json(transfer: &Transfer) -> Value {
+    json!({
+        "from": location_json(transfer.from),
+        "to": location_json(transfer.to),
+        "port": transfer.port.to_string(),
+        "value": transfer.value,
+    })
+}
+
+fn location_json(location: NodeLocation) -> Value {
+    match location {
+        NodeLocation::Grid(idx) => json!({ "node": idx }),
+        NodeLocation::External(idx, port) => json!({ "node": idx, "io": port.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzles::Puzzle;
+
+    #[test]
+    fn test_trace() {
+        // 1 goes in at the top of node 0, down to node 1 where it's doubled, and out the bottom.
+        let puzzle = Puzzle {
+            name: "test",
+            bad_nodes: &[],
+            stack_nodes: &[],
+            inputs: btreemap! { (0, Port::UP) => vec![1] },
+            outputs: btreemap! { (1, Port::DOWN) => vec![3] },
+            visual: btreemap! {},
+            expected: None,
+        };
+        let mut grid = ComputeGrid::from_puzzle(puzzle);
+        let asm = |input: &str| crate::assembly::program_items(input.as_bytes()).unwrap();
+        grid.program_node(0, asm("MOV UP, RIGHT")).unwrap();
+        grid.program_node(1, asm("MOV LEFT, ACC\nADD ACC\nMOV ACC, DOWN")).unwrap();
+
+        let mut trace = TraceWriter::new(vec![], &mut grid);
+        loop {
+            let outcome = grid.step().unwrap();
+            let transfers = grid.take_transfers();
+            trace.write_cycle(&grid, &transfers, outcome.as_ref()).unwrap();
+            if outcome.is_some() {
+                break;
+            }
+        }
+        let text = String::from_utf8(trace.into_inner()).unwrap();
+        let lines = text.lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        let all = |key: &str| lines.iter()
+            .flat_map(|line| line[key].as_array().unwrap().clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(json!(1), lines[0]["cycle"]);
+        assert_eq!(2, lines[0]["nodes"].as_array().unwrap().len());
+        assert_eq!(vec![json!({
+            "from": { "node": 0, "io": "UP" }, "to": { "node": 0 }, "value": 1,
+        })], all("inputs"));
+        assert_eq!(vec![json!({
+            "from": { "node": 0 }, "to": { "node": 1 }, "port": "RIGHT", "value": 1,
+        })], all("transfers"));
+
+        let last = lines.last().unwrap();
+        assert_eq!(json!([{
+            "from": { "node": 1 }, "to": { "node": 1, "io": "DOWN" }, "value": 2, "correct": false,
+        }]), last["outputs"]);
+        assert_eq!(json!("incorrect"), last["outcome"]);
+        assert!(lines.iter().any(|line| line["nodes"][1]["acc"] == json!(2)));
+    }
+}
@@ -0,0 +1,259 @@
+//! Writing a run of the grid as a Value Change Dump, to look at in a waveform viewer like GTKWave.
+//! Each cycle is one time unit.
+
+use crate::grid::{ComputeGrid, NodeLocation, Transfer};
+use crate::instr::Port;
+use crate::node::{CycleStep, NodeType};
+use crate::puzzles::{PUZZLE_WIDTH, PUZZLE_HEIGHT};
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Wide enough for any value a register can hold (-999 to 999), in two's complement.
+const VALUE_BITS: u32 = 11;
+
+/// Writes the grid's signals as they change. Each programmed compute node has its ACC, BAK, pc,
+/// and step (0 for READ, 1 for COMP, 2 for WRTE, 3 for ADVN). Each link a value can be sent over
+/// has the last value sent, and a valid strobe that is 1 for the cycles a value was sent.
+pub struct VcdWriter<W: Write> {
+    out: W,
+    signals: Vec<Signal>,
+    registers: Vec<(usize, [usize; 4])>, // grid index, and signals for acc, bak, pc, and step
+    links: BTreeMap<(NodeLocation, Port), (usize, usize)>, // signals for value and valid
+}
+
+struct Signal {
+    id: String,
+    bits: u32,
+    value: Option<u64>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Write the signal definitions and the starting state of the grid, which should not have run
+    /// yet.
+    pub fn new(out: W, grid: &mut ComputeGrid) -> io::Result<VcdWriter<W>> {
+        grid.enable_transfers();
+        let mut vcd = VcdWriter {
+            out,
+            signals: vec![],
+            registers: vec![],
+            links: BTreeMap::new(),
+        };
+
+        writeln!(vcd.out, "$version tis100 {} $end", env!("CARGO_PKG_VERSION"))?;
+        writeln!(vcd.out, "$timescale 1 us $end")?;
+        writeln!(vcd.out, "$scope module grid $end")?;
+        for idx in 0 .. PUZZLE_WIDTH * PUZZLE_HEIGHT {
+            match grid.node(idx).map(|node| &node.inner) {
+                Some(NodeType::Compute(c)) if !c.instructions.is_empty() => (),
+                _ => continue,
+            }
+            writeln!(vcd.out, "$scope module node{} $end", idx)?;
+            let acc = vcd.add_signal("acc", VALUE_BITS)?;
+            let bak = vcd.add_signal("bak", VALUE_BITS)?;
+            let pc = vcd.add_signal("pc", 8)?;
+            let step = vcd.add_signal("step", 2)?;
+            writeln!(vcd.out, "$upscope $end")?;
+            vcd.registers.push((idx, [acc, bak, pc, step]));
+        }
+
+        writeln!(vcd.out, "$scope module links $end")?;
+        for (from, port, name) in links(grid) {
+            let value = vcd.add_signal(&name, VALUE_BITS)?;
+            let valid = vcd.add_signal(&format!("{}_valid", name), 1)?;
+            vcd.links.insert((from, port), (value, valid));
+        }
+        writeln!(vcd.out, "$upscope $end")?;
+        writeln!(vcd.out, "$upscope $end")?;
+        writeln!(vcd.out, "$enddefinitions $end")?;
+
+        writeln!(vcd.out, "#0")?;
+        writeln!(vcd.out, "$dumpvars")?;
+        vcd.update_registers(grid);
+        for &(value, valid) in vcd.links.values() {
+            vcd.signals[value].value = Some(0);
+            vcd.signals[valid].value = Some(0);
+        }
+        for signal in &vcd.signals {
+            write_value(&mut vcd.out, signal)?;
+        }
+        writeln!(vcd.out, "$end")?;
+        Ok(vcd)
+    }
+
+    fn add_signal(&mut self, name: &str, bits: u32) -> io::Result<usize> {
+        let id = identifier(self.signals.len());
+        writeln!(self.out, "$var wire {} {} {} $end", bits, id, name)?;
+        self.signals.push(Signal { id, bits, value: None });
+        Ok(self.signals.len() - 1)
+    }
+
+    /// Write the signals that changed in the cycle the grid just finished, which passed the given
+    /// values between nodes.
+    pub fn write_cycle(&mut self, grid: &ComputeGrid, transfers: &[Transfer]) -> io::Result<()> {
+        let before = self.signals.iter().map(|signal| signal.value).collect::<Vec<_>>();
+
+        self.update_registers(grid);
+        for &(_value, valid) in self.links.values() {
+            self.signals[valid].value = Some(0);
+        }
+        for transfer in transfers {
+            if let Some(&(value, valid)) = self.links.get(&(transfer.from, transfer.port)) {
+                self.signals[value].value = Some(bits(transfer.value));
+                self.signals[valid].value = Some(1);
+            }
+        }
+
+        let mut changed = self.signals.iter()
+            .zip(before)
+            .filter(|(signal, before)| signal.value != *before)
+            .peekable();
+        if changed.peek().is_some() {
+            writeln!(self.out, "#{}", grid.cycle())?;
+            for (signal, _) in changed {
+                write_value(&mut self.out, signal)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_registers(&mut self, grid: &ComputeGrid) {
+        for &(idx, signals) in &self.registers {
+            let node = grid.node(idx).expect("traced node left the grid");
+            if let NodeType::Compute(c) = &node.inner {
+                let step = match node.step {
+                    CycleStep::Read => 0,
+                    CycleStep::Compute => 1,
+                    CycleStep::Write => 2,
+                    CycleStep::Advance => 3,
+                };
+                let values = [bits(c.acc), bits(c.bak), c.pc as u64, step];
+                for (signal, value) in signals.iter().zip(values) {
+                    self.signals[*signal].value = Some(value);
+                }
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Every link a value could be sent over, by the sending node and the side it sends from, with a
+/// name for it. Links between nodes are named for the sending node and the direction, and links
+/// to and from I/O nodes for the grid node and the side they're attached to.
+fn links(grid: &ComputeGrid) -> Vec<(NodeLocation, Port, String)> {
+    let passes_values = |idx: usize| !matches!(
+        grid.node(idx).map(|node| &node.inner), None | Some(NodeType::Broken(_)));
+
+    let mut links = vec![];
+    for idx in (0 .. PUZZLE_WIDTH * PUZZLE_HEIGHT).filter(|idx| passes_values(*idx)) {
+        let (row, col) = (idx / PUZZLE_WIDTH, idx % PUZZLE_WIDTH);
+        let neighbors = [
+            (Port::UP, (row > 0).then(|| idx - PUZZLE_WIDTH)),
+            (Port::DOWN, (row + 1 < PUZZLE_HEIGHT).then(|| idx + PUZZLE_WIDTH)),
+            (Port::LEFT, (col > 0).then(|| idx - 1)),
+            (Port::RIGHT, (col + 1 < PUZZLE_WIDTH).then(|| idx + 1)),
+        ];
+        for (port, neighbor) in neighbors {
+            if neighbor.is_some_and(passes_values) {
+                links.push((NodeLocation::Grid(idx), port, format!("node{}_{}", idx, port)));
+            }
+        }
+    }
+
+    for (location, node) in grid.external_nodes() {
+        if let NodeLocation::External(idx, port) = location {
+            let name = format!("io{}_{}", idx, port);
+            match node.inner {
+                NodeType::Input(_) => {
+                    let opposite = port.opposite().expect("I/O node on a port with no side");
+                    links.push((location, opposite, name));
+                }
+                _ => links.push((NodeLocation::Grid(idx), port, name)),
+            }
+        }
+    }
+    links
+}
+
+/// A value as the bits of its two's complement.
+fn bits(value: i32) -> u64 {
+    (value as u64) & ((1 << VALUE_BITS) - 1)
+}
+
+fn write_value(out: &mut impl Write, signal: &Signal) -> io::Result<()> {
+    match (signal.bits, signal.value) {
+        (_, None) => Ok(()),
+        (1, Some(value)) => writeln!(out, "{}{}", value, signal.id),
+        (_, Some(value)) => writeln!(out, "b{:b} {}", value, signal.id),
+    }
+}
+
+/// A short name for a signal, made from the printable characters VCD allows.
+fn identifier(mut n: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            return id;
+        }
+        n -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzles::Puzzle;
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!("!", identifier(0));
+        assert_eq!("~", identifier(93));
+        assert_eq!("!!", identifier(94));
+        assert_eq!("\"!", identifier(95));
+    }
+
+    #[test]
+    fn test_vcd() {
+        let puzzle = Puzzle {
+            name: "test",
+            bad_nodes: &[],
+            stack_nodes: &[],
+            inputs: btreemap! { (0, Port::UP) => vec![-1] },
+            outputs: btreemap! { (1, Port::DOWN) => vec![-1] },
+            visual: btreemap! {},
+            expected: None,
+        };
+        let mut grid = ComputeGrid::from_puzzle(puzzle);
+        let asm = |input: &str| crate::assembly::program_items(input.as_bytes()).unwrap();
+        grid.program_node(0, asm("MOV UP, RIGHT")).unwrap();
+        grid.program_node(1, asm("MOV LEFT, DOWN")).unwrap();
+
+        let mut vcd = VcdWriter::new(vec![], &mut grid).unwrap();
+        while grid.step().unwrap().is_none() {
+            let transfers = grid.take_transfers();
+            vcd.write_cycle(&grid, &transfers).unwrap();
+        }
+        let text = String::from_utf8(vcd.into_inner()).unwrap();
+
+        // find a signal's id from its definition
+        let id = |name: &str| text.lines()
+            .find(|line| line.starts_with("$var") && line.ends_with(&format!(" {} $end", name)))
+            .unwrap_or_else(|| panic!("no signal {}", name))
+            .split(' ')
+            .nth(3)
+            .unwrap()
+            .to_owned();
+        assert!(text.contains("$scope module node0 $end"));
+        assert!(!text.contains("$scope module node2 $end"));
+        assert!(text.contains(&format!("b11111111111 {}", id("node0_RIGHT"))));
+        assert!(text.contains(&format!("1{}", id("node0_RIGHT_valid"))));
+        assert!(text.contains(&format!("1{}", id("io0_UP_valid"))));
+        assert!(!text.contains(" node0_UP $end"));
+        assert!(text.contains(" node4_UP $end"));
+    }
+}
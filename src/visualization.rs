@@ -1,9 +1,14 @@
+// num_derive 0.3's FromPrimitive expands to a non-local impl.
+#![allow(non_local_definitions)]
+
 use crate::io::VerifyState;
 use crate::instr::Port;
+use crate::error::Error;
 use crate::node::{StepResult, ReadResult, NodeOps};
 use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive, Serialize, Deserialize)]
 pub enum Color {
     Black = 0,
     DarkGray = 1,
@@ -12,7 +17,7 @@ pub enum Color {
     Red = 4,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VisualizationNode {
     expected: Vec<Color>,
     values: Vec<Color>,
@@ -43,9 +48,27 @@ impl VisualizationNode {
         self.verified
     }
 
-    fn handle_value(&mut self, value: i32) -> VerifyState {
+    /// Check that the image is the size it says, and the cursor is on it.
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let size = self.width * self.height;
+        if self.values.len() != size || self.expected.len() != size {
+            return Err(format!("{}x{} image has {} values and {} expected", self.width,
+                self.height, self.values.len(), self.expected.len()));
+        }
+        let in_image = match self.cursor {
+            None => true,
+            Some((x, None)) => x < self.width,
+            Some((x, Some(y))) => x < self.width && y < self.height,
+        };
+        if !in_image {
+            return Err(format!("cursor {:?} is outside the image", self.cursor));
+        }
+        Ok(())
+    }
+
+    fn handle_value<const LOG: bool>(&mut self, value: i32) -> VerifyState {
         if value == -1 {
-            info!("resetting cursor");
+            log_if!(LOG, info, "resetting cursor");
             self.cursor = None;
             return VerifyState::Okay;
         }
@@ -53,27 +76,28 @@ impl VisualizationNode {
         let new_cursor = match self.cursor {
             None => {
                 if !in_bounds(value, self.width) {
-                    error!("out-of-bounds X value {}", value);
+                    log_if!(LOG, error, "out-of-bounds X value {}", value);
                     return VerifyState::Failed
                 }
-                info!("cursor X value set to {}", value);
+                log_if!(LOG, info, "cursor X value set to {}", value);
                 Some((value as usize, None))
             },
             Some((x, None)) => {
                 if !in_bounds(value, self.height) {
-                    error!("out-of-bounds Y value {}", value);
+                    log_if!(LOG, error, "out-of-bounds Y value {}", value);
                     return VerifyState::Failed;
                 }
-                info!("cursor Y value set to {}", value);
+                log_if!(LOG, info, "cursor Y value set to {}", value);
                 Some((x, Some(value as usize)))
             }
             Some((mut x, Some(y))) => {
                 if let Some(color) = Color::from_i32(value) {
                     let idx = y * self.width + x;
-                    info!("setting {},{} (offset {}) to {:?}", x, y, idx, color);
+                    log_if!(LOG, info, "setting {},{} (offset {}) to {:?}", x, y, idx, color);
 
                     if self.expected[idx] != color {
-                        error!("set to wrong color: expected {:?}", self.expected[idx]);
+                        log_if!(LOG, error, "set to wrong color: expected {:?}",
+                            self.expected[idx]);
                         // NOTE: it's possible that the correct color could be set later.
                         // The game itself does not abort when a wrong color is set.
                         // (It actually doesn't seem to abort ever due to a visualization node...)
@@ -82,7 +106,7 @@ impl VisualizationNode {
 
                     self.values[idx] = color;
                 } else {
-                    error!("invalid color value {}", value);
+                    log_if!(LOG, error, "invalid color value {}", value);
                     return VerifyState::Failed;
                 }
 
@@ -94,7 +118,7 @@ impl VisualizationNode {
         };
 
         if self.values == self.expected {
-            info!("all done!");
+            log_if!(LOG, info, "all done!");
             return VerifyState::Finished;
         }
 
@@ -104,17 +128,34 @@ impl VisualizationNode {
 }
 
 impl NodeOps for VisualizationNode {
-    fn read(&mut self, avail_reads: &mut [(Port, Option<i32>)]) -> ReadResult {
+    fn read<const LOG: bool>(&mut self, avail_reads: &mut [(Port, Option<i32>)])
+        -> Result<ReadResult, Error>
+    {
         if let Some((port, val)) = avail_reads.get_mut(0) {
             let val = val.take().unwrap();
-            info!("handling value {} from {}", val, port);
-            let state = self.handle_value(val);
+            log_if!(LOG, info, "handling value {} from {}", val, port);
+            let state = self.handle_value::<LOG>(val);
             self.verified = state;
-            state.as_read_result()
+            Ok(state.as_read_result())
         } else {
-            StepResult::IO(Port::ANY)
+            Ok(StepResult::IO(Port::ANY))
         }
     }
 
     // default impls for compute, write, and advance
 }
+
+impl std::fmt::Display for VisualizationNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let correct = self.values.iter()
+            .zip(&self.expected)
+            .filter(|(value, expected)| value == expected)
+            .count();
+        let cursor = match self.cursor {
+            None => "unset".to_owned(),
+            Some((x, None)) => format!("{},?", x),
+            Some((x, Some(y))) => format!("{},{}", x, y),
+        };
+        f.pad(&format!("{}/{} pixels correct, cursor at {}", correct, self.expected.len(), cursor))
+    }
+}
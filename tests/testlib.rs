@@ -3,10 +3,16 @@
 
 use rand::SeedableRng;
 
-use tis100::grid::ComputeGrid;
+use tis100::error::{Error, ErrorKind};
+use tis100::grid::{BlockedNode, BreakpointHit, ComputeGrid, Mismatch, NodeLocation, NodeStats,
+    Outcome, StopReason};
+use tis100::node::{CycleStep, NodeType};
+use tis100::profile::InstructionProfile;
 use tis100::instr::*;
 use tis100::puzzles::Puzzle;
 
+use std::collections::BTreeMap;
+
 fn rng() -> impl rand::Rng + Clone {
     rand_chacha::ChaChaRng::from_seed([0;32])
 }
@@ -68,10 +74,10 @@ fn out_of_range_immediate() {
 fn run(grid: &mut ComputeGrid, expected_cycles: usize) {
     let mut cycle = 1;
     loop {
-        match grid.step() {
+        match grid.step().unwrap() {
             None => { cycle += 1; }
-            Some(true) => { break; }
-            Some(false) => { panic!("incorrect result on cycle {}", cycle); }
+            Some(Outcome::Correct) => { break; }
+            Some(outcome) => { panic!("{:?} on cycle {}", outcome, cycle); }
         }
         if cycle > expected_cycles {
             panic!("too many (>{}) cycles", expected_cycles);
@@ -89,6 +95,7 @@ fn connectivity_test() {
         inputs: btreemap! { (0, Port::UP) => vec![1,2,3,4] },
         outputs: btreemap! { (11, Port::DOWN) => vec![1,2,3,4] },
         visual: btreemap! {},
+        expected: None,
     };
     let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
 
@@ -98,18 +105,18 @@ fn connectivity_test() {
     // ↓ ← ← ←
     // → → → ↓
     //       Out
-    grid.program_node(0, asm("MOV ANY, RIGHT"));
-    grid.program_node(1, asm("MOV ANY, RIGHT"));
-    grid.program_node(2, asm("MOV ANY, RIGHT"));
-    grid.program_node(3, asm("MOV ANY, DOWN"));
-    grid.program_node(7, asm("MOV ANY, LEFT"));
-    grid.program_node(6, asm("MOV ANY, LEFT"));
-    grid.program_node(5, asm("MOV ANY, LEFT"));
-    grid.program_node(4, asm("MOV ANY, DOWN"));
-    grid.program_node(8, asm("MOV ANY, RIGHT"));
-    grid.program_node(9, asm("MOV ANY, RIGHT"));
-    grid.program_node(10, asm("MOV ANY, RIGHT"));
-    grid.program_node(11, asm("MOV ANY, DOWN"));
+    grid.program_node(0, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(1, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(2, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(3, asm("MOV ANY, DOWN")).unwrap();
+    grid.program_node(7, asm("MOV ANY, LEFT")).unwrap();
+    grid.program_node(6, asm("MOV ANY, LEFT")).unwrap();
+    grid.program_node(5, asm("MOV ANY, LEFT")).unwrap();
+    grid.program_node(4, asm("MOV ANY, DOWN")).unwrap();
+    grid.program_node(8, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(9, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(10, asm("MOV ANY, RIGHT")).unwrap();
+    grid.program_node(11, asm("MOV ANY, DOWN")).unwrap();
 
     run(&mut grid, 20);
 }
@@ -146,7 +153,7 @@ MOV ANY,DOWN";
     let puzzle = tis100::puzzles::get_puzzle("DBG01", rng()).unwrap();
     let mut grid = tis100::grid::ComputeGrid::from_puzzle(puzzle);
     let nodes = tis100::assembly::parse_save_file(asm).unwrap();
-    grid.program_nodes(nodes);
+    grid.program_nodes(nodes).unwrap();
 
     run(&mut grid, 90);
 }
@@ -164,9 +171,637 @@ fn stack_test() {
     // Out
     grid.program_node(0, asm("
         MOV UP,RIGHT\nMOV UP,RIGHT\nMOV UP,RIGHT\nMOV UP,RIGHT
-        MOV RIGHT,DOWN\nMOV RIGHT,DOWN\nMOV RIGHT,DOWN\nMOV RIGHT,DOWN"));
-    grid.program_node(4, asm("MOV UP, DOWN"));
-    grid.program_node(8, asm("MOV UP, DOWN"));
+        MOV RIGHT,DOWN\nMOV RIGHT,DOWN\nMOV RIGHT,DOWN\nMOV RIGHT,DOWN")).unwrap();
+    grid.program_node(4, asm("MOV UP, DOWN")).unwrap();
+    grid.program_node(8, asm("MOV UP, DOWN")).unwrap();
 
     run(&mut grid, 19);
 }
+
+fn run_to_deadlock(grid: &mut ComputeGrid, max_cycles: usize) -> Vec<BlockedNode> {
+    for cycle in 1 ..= max_cycles {
+        match grid.step().unwrap() {
+            None => (),
+            Some(Outcome::Deadlock(blocked)) => { return blocked; }
+            Some(outcome) => { panic!("{:?} on cycle {}", outcome, cycle); }
+        }
+    }
+    panic!("no deadlock after {} cycles", max_cycles);
+}
+
+#[test]
+fn deadlock_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    // Node 0 writes to node 4, which never reads it.
+    grid.program_node(0, asm("MOV UP, DOWN")).unwrap();
+    grid.program_node(4, asm("MOV 1, ACC")).unwrap();
+
+    let blocked = run_to_deadlock(&mut grid, 10);
+    assert_eq!(
+        vec![
+            BlockedNode {
+                location: NodeLocation::Grid(0),
+                type_name: "compute",
+                step: CycleStep::Write,
+                port: Port::DOWN,
+            },
+            BlockedNode {
+                location: NodeLocation::External(0, Port::UP),
+                type_name: "input",
+                step: CycleStep::Write,
+                port: Port::DOWN,
+            },
+            BlockedNode {
+                location: NodeLocation::External(8, Port::DOWN),
+                type_name: "output",
+                step: CycleStep::Read,
+                port: Port::UP,
+            },
+        ],
+        blocked);
+}
+
+#[test]
+fn input_exhausted_deadlock_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    // Node 8 swallows all the input, so once it runs out, everything waits forever.
+    grid.program_node(0, asm("MOV UP, DOWN")).unwrap();
+    grid.program_node(4, asm("MOV UP, DOWN")).unwrap();
+    grid.program_node(8, asm("MOV UP, ACC")).unwrap();
+
+    let blocked = run_to_deadlock(&mut grid, 30);
+    assert_eq!(
+        vec![
+            BlockedNode {
+                location: NodeLocation::Grid(0),
+                type_name: "compute",
+                step: CycleStep::Read,
+                port: Port::UP,
+            },
+            BlockedNode {
+                location: NodeLocation::Grid(4),
+                type_name: "compute",
+                step: CycleStep::Read,
+                port: Port::UP,
+            },
+            BlockedNode {
+                location: NodeLocation::Grid(8),
+                type_name: "compute",
+                step: CycleStep::Read,
+                port: Port::UP,
+            },
+            BlockedNode {
+                location: NodeLocation::External(8, Port::DOWN),
+                type_name: "output",
+                step: CycleStep::Read,
+                port: Port::UP,
+            },
+        ],
+        blocked);
+}
+
+fn run_to_error(grid: &mut ComputeGrid, max_cycles: usize) -> Error {
+    for cycle in 1 ..= max_cycles {
+        match grid.step() {
+            Ok(None) => (),
+            Ok(Some(outcome)) => { panic!("{:?} on cycle {}", outcome, cycle); }
+            Err(e) => { return e; }
+        }
+    }
+    panic!("no error after {} cycles", max_cycles);
+}
+
+#[test]
+fn undefined_label_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    assert_eq!(
+        Error {
+            location: Some(NodeLocation::Grid(4)),
+            pc: Some(1),
+            instruction: Some(Instruction::JMP("NOWHERE".to_owned())),
+            kind: ErrorKind::UndefinedLabel("NOWHERE".to_owned()),
+        },
+        grid.program_node(4, asm("HERE: NOP\nJMP NOWHERE")).unwrap_err());
+}
+
+#[test]
+fn no_such_node_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    // Node 1 is a stack node, so there are only 11 programmable nodes.
+    let save = (0 ..= 11).map(|n| format!("@{}\nNOP\n", n)).collect::<String>();
+    let nodes = tis100::assembly::parse_save_file(save.as_bytes()).unwrap();
+    assert_eq!(
+        ErrorKind::NoSuchNode(SaveFileNodeId(11)),
+        grid.program_nodes(nodes).unwrap_err().kind);
+}
+
+#[test]
+fn unset_last_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    grid.program_node(0, asm("NOP\nMOV LAST, ACC")).unwrap();
+    assert_eq!(
+        Error {
+            location: Some(NodeLocation::Grid(0)),
+            pc: Some(1),
+            instruction: Some(Instruction::MOV(
+                Src::Port(Port::LAST),
+                Dst::Register(Register::ACC))),
+            kind: ErrorKind::UnsetLastPort,
+        },
+        run_to_error(&mut grid, 3));
+}
+
+#[test]
+fn hcf_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    grid.program_node(5, asm("HCF")).unwrap();
+    let err = run_to_error(&mut grid, 1);
+    assert_eq!(Some(NodeLocation::Grid(5)), err.location);
+    assert_eq!(ErrorKind::HaltAndCatchFire, err.kind);
+}
+
+#[test]
+fn breakpoint_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    grid.program_node(0, asm("MOV UP, ACC\nL:\n!ADD 1\n!MOV ACC, DOWN\nJMP L")).unwrap();
+    grid.program_node(4, asm("MOV UP, NIL")).unwrap();
+
+    // The first instruction reads the input, which is only available in the second cycle.
+    assert_eq!(
+        StopReason::Breakpoint(vec![BreakpointHit { node: 0, pc: 1 }]),
+        grid.resume().unwrap());
+    assert_eq!(2, grid.cycle());
+
+    assert_eq!(
+        StopReason::Breakpoint(vec![BreakpointHit { node: 0, pc: 2 }]),
+        grid.resume().unwrap());
+    assert_eq!(3, grid.cycle());
+
+    // The MOV gets blocked for a cycle while node 4 picks up the value, then the jump goes back to
+    // the first breakpoint.
+    assert_eq!(StopReason::Stepped, grid.run_until_breakpoint(Some(2)).unwrap());
+    assert_eq!(5, grid.cycle());
+    assert_eq!(
+        StopReason::Breakpoint(vec![BreakpointHit { node: 0, pc: 1 }]),
+        grid.resume().unwrap());
+    assert_eq!(6, grid.cycle());
+}
+
+#[test]
+fn step_phase_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    grid.program_node(5, asm("NOP\n!NOP")).unwrap();
+
+    assert_eq!(CycleStep::Read, grid.phase());
+    for phase in &[CycleStep::Compute, CycleStep::Write, CycleStep::Advance] {
+        assert_eq!(StopReason::Stepped, grid.debug_step_phase().unwrap());
+        assert_eq!(*phase, grid.phase());
+        assert_eq!(0, grid.cycle());
+    }
+    assert_eq!(
+        StopReason::Breakpoint(vec![BreakpointHit { node: 5, pc: 1 }]),
+        grid.debug_step_phase().unwrap());
+    assert_eq!(CycleStep::Read, grid.phase());
+    assert_eq!(1, grid.cycle());
+
+    assert_eq!(StopReason::Stepped, grid.debug_step_phase().unwrap());
+    assert_eq!(StopReason::Stepped, grid.debug_step_cycle().unwrap());
+    assert_eq!(CycleStep::Read, grid.phase());
+    assert_eq!(2, grid.cycle());
+}
+
+#[test]
+fn duplicate_label_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+
+    assert_eq!(
+        Error {
+            location: Some(NodeLocation::Grid(4)),
+            pc: None,
+            instruction: None,
+            kind: ErrorKind::DuplicateLabel("HERE".to_owned()),
+        },
+        grid.program_node(4, asm("HERE: NOP\nHERE: JMP HERE")).unwrap_err());
+}
+
+/// Everything about the state of the grid that running it can change.
+fn grid_state(grid: &ComputeGrid) -> Vec<String> {
+    let mut state = vec![];
+    for idx in 0 .. 12 {
+        let node = grid.node(idx).unwrap();
+        let inner = match &node.inner {
+            NodeType::Compute(c) => {
+                format!("{} {} {} {} {:?}", c.pc, c.acc, c.bak, c.last, c.read_result)
+            }
+            NodeType::Stack(s) => format!("{:?}", s.values()),
+            _ => String::new(),
+        };
+        state.push(format!("{}: {} {:?} {}", idx, node.step, node.pending_output, inner));
+    }
+    for (location, node) in grid.external_nodes() {
+        state.push(format!("{}: {:?}", location, node));
+    }
+    state.push(format!("{:?}", grid.node_stats()));
+    state
+}
+
+const PORTS: &[&str] = &["UP", "DOWN", "LEFT", "RIGHT", "ANY", "LAST"];
+
+fn random_src(rng: &mut impl rand::Rng) -> String {
+    match rng.gen_range(0 .. 20) {
+        0 | 1 => "ACC".to_owned(),
+        2 | 3 => "NIL".to_owned(),
+        4 ..= 7 => rng.gen_range(-3 .. 4).to_string(),
+        // LAST only rarely, since it usually fails right away
+        8 => "LAST".to_owned(),
+        _ => PORTS[rng.gen_range(0 .. 5)].to_owned(),
+    }
+}
+
+/// A random program for a compute node. Every instruction has a label, so jumps can go anywhere.
+fn random_program(rng: &mut impl rand::Rng) -> String {
+    let len = rng.gen_range(1 ..= 8);
+    let mut program = String::new();
+    for i in 0 .. len {
+        let label = format!("L{}", rng.gen_range(0 .. len));
+        let instr = match rng.gen_range(0 .. 16) {
+            0 => "NOP".to_owned(),
+            1 => "SWP".to_owned(),
+            2 => "SAV".to_owned(),
+            3 => "NEG".to_owned(),
+            4 => format!("ADD {}", random_src(rng)),
+            5 => format!("SUB {}", random_src(rng)),
+            6 => format!("JMP {}", label),
+            7 => format!("JEZ {}", label),
+            8 => format!("JNZ {}", label),
+            9 => format!("JGZ {}", label),
+            10 => format!("JLZ {}", label),
+            11 => format!("JRO {}", random_src(rng)),
+            12 => format!("MOV {}, ACC", random_src(rng)),
+            _ => format!("MOV {}, {}", random_src(rng), PORTS[rng.gen_range(0 .. 6)]),
+        };
+        program += &format!("L{}: {}\n", i, instr);
+    }
+    program
+}
+
+/// Run random programs with and without skipping blocked nodes, and check that the grids stay
+/// exactly the same every cycle.
+#[test]
+fn scheduler_differential_test() {
+    let mut program_rng = rng();
+    for round in 0 .. 300 {
+        let puzzle_num = ["DBG02", "10981", "20176", "21340"][round % 4];
+        let mut grids = [true, false].iter()
+            .map(|&skip| {
+                let puzzle = tis100::puzzles::get_puzzle(puzzle_num, rng()).unwrap();
+                let mut grid = ComputeGrid::from_puzzle(puzzle);
+                grid.set_skip_blocked(skip);
+                grid
+            })
+            .collect::<Vec<_>>();
+
+        let mut programs = vec![];
+        for idx in 0 .. 12 {
+            let program = random_program(&mut program_rng);
+            // broken and stack nodes take no program
+            let compute = matches!(grids[0].node(idx).unwrap().inner, NodeType::Compute(_));
+            for grid in &mut grids {
+                assert_eq!(compute, grid.program_node(idx, asm(&program)).unwrap());
+            }
+            programs.push(program);
+        }
+
+        for cycle in 1 ..= 200 {
+            let fast = grids[0].step();
+            let reference = grids[1].step();
+            assert_eq!(reference, fast, "round {} cycle {}: {:#?}", round, cycle, programs);
+            assert_eq!(grid_state(&grids[1]), grid_state(&grids[0]),
+                "round {} cycle {}: {:#?}", round, cycle, programs);
+            if !matches!(reference, Ok(None)) {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn run_to_completion_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+    grid.program_node(0, asm("MOV UP, RIGHT")).unwrap();
+    grid.program_node(4, asm("NOP")).unwrap();
+
+    // No cycles at all, from the start and from partway through a cycle.
+    assert_eq!((None, 0), grid.run_to_completion(Some(0)).unwrap());
+    assert_eq!(CycleStep::Read, grid.phase());
+    grid.step_phase().unwrap();
+    assert_eq!((None, 0), grid.run_to_completion(Some(0)).unwrap());
+    assert_eq!(CycleStep::Compute, grid.phase());
+
+    // Partway through a cycle, then a limited number of cycles, then the rest.
+    assert_eq!((None, 5), grid.run_to_completion(Some(5)).unwrap());
+    assert_eq!(CycleStep::Read, grid.phase());
+    let (outcome, cycles) = grid.run_to_completion(None).unwrap();
+    assert!(matches!(outcome, Some(Outcome::Deadlock(_))), "{:?}", outcome);
+
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut stepped = tis100::grid::ComputeGrid::from_puzzle(puz);
+    stepped.program_node(0, asm("MOV UP, RIGHT")).unwrap();
+    stepped.program_node(4, asm("NOP")).unwrap();
+    let blocked = run_to_deadlock(&mut stepped, 100);
+    assert_eq!((Some(Outcome::Deadlock(blocked)), stepped.cycle()), (outcome, cycles));
+}
+
+#[test]
+fn node_stats_test() {
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+    grid.program_node(0, asm("MOV UP, ACC\nMOV ACC, DOWN")).unwrap();
+    grid.program_node(4, asm("NOP\nNOP\nNOP\nMOV UP, DOWN")).unwrap();
+    grid.program_node(8, asm("MOV UP, DOWN")).unwrap();
+    for _ in 0 .. 5 {
+        assert_eq!(None, grid.step().unwrap());
+    }
+
+    let stats = grid.node_stats();
+    // waits for the first input, reads it, waits for node 4 to take it, and reads the next one
+    assert_eq!(NodeStats { executing: 3, blocked_read: 1, blocked_write: 1, idle: 0 }, stats[0]);
+    // three NOPs, then reads and waits for node 8 to take the value
+    assert_eq!(NodeStats { executing: 4, blocked_read: 0, blocked_write: 1, idle: 0 }, stats[4]);
+    // waits for node 4, then reads and waits for the output to take the value
+    assert_eq!(NodeStats { executing: 0, blocked_read: 4, blocked_write: 1, idle: 0 }, stats[8]);
+    assert_eq!(NodeStats { executing: 0, blocked_read: 0, blocked_write: 0, idle: 5 }, stats[5]);
+    assert_eq!(NodeStats::default(), stats[1]);
+    assert_eq!(100., stats[8].idle_percent());
+    assert_eq!(40., stats[0].idle_percent());
+}
+
+#[test]
+fn profile_test() {
+    let input = b"@0\nMOV UP, ACC\nMOV ACC, DOWN\n@1\n@2\n@3\nNOP\nNOP\nNOP\nMOV UP, DOWN\n\
+        @4\n@5\n@6\n@7\nMOV UP, DOWN\n";
+    let file = tis100::syntax::SourceFile::parse(input).unwrap();
+    let puz = tis100::puzzles::get_puzzle("DBG02", rng()).unwrap();
+    let mut grid = tis100::grid::ComputeGrid::from_puzzle(puz);
+    grid.program_nodes(file.nodes()).unwrap();
+    assert_eq!(None, grid.profile(0));
+    grid.enable_profile();
+    for _ in 0 .. 5 {
+        assert_eq!(None, grid.step().unwrap());
+    }
+
+    assert_eq!(Some(4), grid.grid_index(SaveFileNodeId(3)));
+    assert_eq!(Some(8), grid.grid_index(SaveFileNodeId(7)));
+    assert_eq!(None, grid.profile(5));
+    let profile = |exec, read, write| InstructionProfile {
+        executions: exec,
+        blocked_read: read,
+        blocked_write: write,
+    };
+    // the same cycles as in node_stats_test, split up by instruction
+    assert_eq!(Some(&[profile(2, 1, 0), profile(1, 0, 1)][..]), grid.profile(0));
+    assert_eq!(Some(&[profile(1, 0, 0), profile(1, 0, 0), profile(1, 0, 0), profile(1, 0, 1)][..]),
+        grid.profile(4));
+    assert_eq!(Some(&[profile(0, 4, 1)][..]), grid.profile(8));
+    assert_eq!(vec![(8, 0, profile(0, 4, 1)), (0, 0, profile(2, 1, 0))],
+        tis100::profile::hot_spots(&grid, 2));
+
+    let annotated = tis100::profile::annotate(&file, &grid);
+    let lines = annotated.lines().collect::<Vec<_>>();
+    assert_eq!("    EXEC    READ   WRITE |", lines[0]);
+    assert_eq!("                         | @0", lines[1]);
+    assert_eq!("       2       1       0 | MOV UP, ACC", lines[2]);
+    assert_eq!("       0       4       1 | MOV UP, DOWN", lines[15]);
+}
+
+#[test]
+fn seeded_rng_test() {
+    fn inputs(rng: impl rand::Rng + Clone + 'static) -> BTreeMap<(usize, Port), Vec<i32>> {
+        tis100::puzzles::get_puzzle("10981", rng).unwrap().inputs
+    }
+    // seed 0 is the same as the all-zero seed used everywhere else
+    assert_eq!(inputs(rng()), inputs(tis100::puzzles::seeded_rng(0)));
+    assert_ne!(inputs(rng()), inputs(tis100::puzzles::seeded_rng(1)));
+}
+
+#[test]
+fn save_file_slot_test() {
+    for number in tis100::puzzles::PUZZLE_NUMBERS {
+        assert!(tis100::puzzles::get_puzzle(number, rng()).is_some(), "{}", number);
+    }
+    assert_eq!(Some(("10981", 0)), tis100::puzzles::save_file_slot("10981.0.txt"));
+    assert_eq!(Some(("53897", 12)), tis100::puzzles::save_file_slot("53897.12.txt"));
+    assert_eq!(None, tis100::puzzles::save_file_slot("99999.0.txt"));
+    assert_eq!(None, tis100::puzzles::save_file_slot("10981.txt"));
+    assert_eq!(None, tis100::puzzles::save_file_slot("10981.x.txt"));
+    assert_eq!(None, tis100::puzzles::save_file_slot("10981.0.txt.bak"));
+}
+
+#[test]
+fn expected_outputs_test() {
+    let puzzle = |number, seed| {
+        tis100::puzzles::get_puzzle(number, tis100::puzzles::seeded_rng(seed)).unwrap()
+    };
+    for number in tis100::puzzles::PUZZLE_NUMBERS {
+        if puzzle(number, 0).expected.is_none() {
+            // only puzzles with image outputs can't work out their outputs
+            assert!(!puzzle(number, 0).visual.is_empty(), "{}", number);
+            continue;
+        }
+        for seed in 0 .. 50 {
+            let puzzle = puzzle(number, seed);
+            let expected = puzzle.expected.unwrap();
+            assert_eq!(puzzle.outputs, expected(&puzzle.inputs), "{} seed {}", number, seed);
+        }
+    }
+}
+
+#[test]
+fn sequence_reverser_test() {
+    // The last random value for seed 4 is a 0, which used to add a second 0 to the end of the
+    // outputs.
+    let puzzle = tis100::puzzles::get_puzzle("42656", tis100::puzzles::seeded_rng(4)).unwrap();
+    let input = &puzzle.inputs[&(1, Port::UP)];
+    let output = &puzzle.outputs[&(10, Port::DOWN)];
+    let zeros = |values: &[i32]| values.iter().filter(|n| **n == 0).count();
+    assert_eq!(input.len(), output.len());
+    assert_eq!(zeros(input), zeros(output));
+}
+
+#[test]
+fn snapshot_test() {
+    let mut program_rng = rng();
+    for round in 0 .. 40 {
+        let puzzle_num = ["DBG02", "10981", "20176", "21340"][round % 4];
+        let puzzle = tis100::puzzles::get_puzzle(puzzle_num, rng()).unwrap();
+        let mut grid = ComputeGrid::from_puzzle(puzzle);
+        grid.enable_profile();
+        grid.enable_transfers();
+        for idx in 0 .. 12 {
+            // broken and stack nodes take no program
+            let compute = matches!(grid.node(idx).unwrap().inner, NodeType::Compute(_));
+            let program = asm(&random_program(&mut program_rng));
+            assert_eq!(compute, grid.program_node(idx, program).unwrap());
+        }
+
+        // Partway through a cycle, so the pending reads and writes have to be kept too.
+        let _ = grid.run_to_completion(Some(20));
+        let _ = grid.step_phase();
+        let snapshot = grid.snapshot();
+        let mut file = vec![];
+        snapshot.write(&mut file).unwrap();
+        let mut restored = tis100::snapshot::Snapshot::read(&file[..]).unwrap().to_grid();
+        assert_eq!(grid.cycle(), restored.cycle());
+        assert_eq!(grid.phase(), restored.phase());
+
+        let state = grid_state(&grid);
+        for cycle in 1 ..= 100 {
+            let result = grid.step();
+            assert_eq!(result, restored.step(), "round {} cycle {}", round, cycle);
+            assert_eq!(grid_state(&grid), grid_state(&restored), "round {} cycle {}", round, cycle);
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+        assert_eq!(grid.take_transfers(), restored.take_transfers());
+        for idx in 0 .. 12 {
+            assert_eq!(grid.profile(idx), restored.profile(idx));
+        }
+
+        grid.restore(&snapshot);
+        assert_eq!(state, grid_state(&grid));
+    }
+}
+
+#[test]
+fn history_test() {
+    use tis100::history::{Found, History};
+
+    let new_grid = || {
+        let puz = tis100::puzzles::get_puzzle("10981", rng()).unwrap();
+        let mut grid = ComputeGrid::from_puzzle(puz);
+        grid.program_node(1, asm("MOV UP, DOWN")).unwrap();
+        grid.program_node(5, asm("MOV UP, ACC\nADD ACC\nMOV ACC, DOWN")).unwrap();
+        grid.program_node(9, asm("MOV UP, RIGHT")).unwrap();
+        grid.program_node(10, asm("MOV LEFT, DOWN")).unwrap();
+        grid
+    };
+    let run_to = |cycle| {
+        let mut grid = new_grid();
+        grid.run_to_completion(Some(cycle)).unwrap();
+        grid
+    };
+
+    // small enough limits that checkpoints get thinned out
+    let mut grid = new_grid();
+    let mut history = History::with_limits(&grid, 7, 4);
+    assert_eq!(StopReason::Stepped, history.run_until_breakpoint(&mut grid, Some(120)).unwrap());
+    assert_eq!(120, grid.cycle());
+    assert!(history.checkpoints() <= 4);
+
+    history.step_back(&mut grid, 1).unwrap();
+    assert_eq!(grid_state(&run_to(119)), grid_state(&grid));
+    history.rewind_to(&mut grid, 33).unwrap();
+    assert_eq!(grid_state(&run_to(33)), grid_state(&grid));
+    history.step_back(&mut grid, 1000).unwrap();
+    assert_eq!(grid_state(&new_grid()), grid_state(&grid));
+
+    // the last write and ACC change before cycle 100, found by running forward
+    let mut forward = new_grid();
+    forward.enable_transfers();
+    let (mut last_write, mut last_acc) = (None, None);
+    while forward.cycle() < 100 {
+        let acc = |grid: &ComputeGrid| match &grid.node(5).unwrap().inner {
+            NodeType::Compute(c) => c.acc,
+            _ => unreachable!(),
+        };
+        let before = acc(&forward);
+        forward.step().unwrap();
+        for transfer in forward.take_transfers() {
+            if transfer.from == NodeLocation::Grid(5) && transfer.port == Port::DOWN {
+                last_write = Some((forward.cycle(), transfer.value));
+            }
+        }
+        if acc(&forward) != before {
+            last_acc = Some((forward.cycle(), before, acc(&forward)));
+        }
+    }
+    let (write_cycle, value) = last_write.unwrap();
+    let (acc_cycle, from, to) = last_acc.unwrap();
+
+    history.rewind_to(&mut grid, 100).unwrap();
+    assert_eq!(Some(Found::Write { node: 5, port: Port::DOWN, value }),
+        history.last_write(&mut grid, 5, Port::DOWN).unwrap());
+    assert_eq!(grid_state(&run_to(write_cycle - 1)), grid_state(&grid));
+
+    history.rewind_to(&mut grid, 100).unwrap();
+    assert_eq!(Some(Found::Acc { node: 5, from, to }),
+        history.last_acc_change(&mut grid, 5).unwrap());
+    assert_eq!(acc_cycle - 1, grid.cycle());
+
+    // nothing to find
+    assert_eq!(None, history.last_write(&mut grid, 5, Port::UP).unwrap());
+    assert_eq!(acc_cycle - 1, grid.cycle());
+}
+
+#[test]
+fn mismatch_test() {
+    let puz = Puzzle {
+        name: "test",
+        bad_nodes: &[],
+        stack_nodes: &[],
+        inputs: btreemap! { (0, Port::UP) => vec![1, 2, 3, 4] },
+        outputs: btreemap! { (0, Port::DOWN) => vec![1, 2, 3, 4] },
+        visual: btreemap! {},
+        expected: None,
+    };
+    let mut grid = ComputeGrid::from_puzzle(puz);
+    grid.program_node(0, asm("MOV UP, DOWN\nMOV UP, NIL")).unwrap();
+    let (outcome, cycles) = grid.run_to_completion(Some(100)).unwrap();
+
+    // passes on 1, drops 2, and sends 3 in its place
+    let mismatch = match outcome {
+        Some(Outcome::Incorrect(Some(mismatch))) => mismatch,
+        other => panic!("wrong outcome: {:?}", other),
+    };
+    assert_eq!(Mismatch {
+        node: 0,
+        port: Port::DOWN,
+        position: 1,
+        expected: 2,
+        received: 3,
+        cycle: cycles,
+        expected_values: vec![1, 2, 3, 4],
+        received_values: vec![1, 3],
+    }, mismatch);
+    assert_eq!(
+        format!("DOWN of node 0 received 3 at position 1 in cycle {}, but expected 2", cycles),
+        mismatch.to_string());
+    assert_eq!(
+        vec![
+            "      EXPECTED RECEIVED",
+            "    0        1        1",
+            "    1        2        3  <--",
+            "    2        3",
+            "    3        4",
+        ],
+        mismatch.side_by_side().lines().collect::<Vec<_>>());
+}